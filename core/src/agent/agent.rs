@@ -1,37 +1,431 @@
-use crate::config::OllamaConfig;
+use crate::agent::context::{self, ContextChunk, ContextStore};
+use crate::agent::session::Session;
+use crate::config::{AppConfig, ProviderKind};
 use crate::error::{AppError, Result};
-use crate::llm::{LlmProvider, OllamaClient, ResponseStream};
+use crate::llm::{
+    self, Capability, LlmProvider, Message, OllamaClient, OpenAiClient, ProviderCapabilities,
+    PullStream, ResponseStream, Role,
+};
+use std::path::Path;
 
 pub struct Agent {
-    llm: Box<dyn LlmProvider>,
+    /// The configured provider first, then fallbacks in the order they are tried.
+    providers: Vec<Box<dyn LlmProvider>>,
+    /// Indexes of fallbacks without their own model, which use the primary's.
+    model_followers: Vec<usize>,
+    max_history_messages: Option<usize>,
+    system_prompt: Option<String>,
+    /// Seeded as the start of every reply.
+    response_prefix: Option<String>,
+    needs_model_pull: bool,
+    context: ContextStore,
+    context_top_k: usize,
+    stop: Vec<String>,
 }
 
 impl Agent {
-    pub fn new(cfg: &OllamaConfig) -> Result<Self> {
-        tracing::info!("Initializing agent with model: {}", cfg.model_name);
+    pub fn new(cfg: &AppConfig) -> Result<Self> {
+        let llm = Self::build_provider(cfg)?;
 
-        let ollama = OllamaClient::new(
-            &cfg.base_url,
-            &cfg.model_name,
-            cfg.timeout_secs,
-            cfg.max_retries,
-        )?;
+        let mut agent = Self::with_provider(llm);
+        for fallback in &cfg.fallback_providers {
+            let client = OllamaClient::new(&fallback.ollama_config(&cfg.ollama))?
+                .with_log_truncation(cfg.truncation.clone());
+            tracing::info!("Fallback provider: {}", client.describe());
+            if fallback.follows_primary_model() {
+                agent.model_followers.push(agent.providers.len());
+            }
+            agent.providers.push(Box::new(client));
+        }
+        agent.max_history_messages = cfg.ollama.max_history_messages;
+        agent.system_prompt = cfg.ollama.system_prompt.clone();
+        agent.set_response_prefix(cfg.response_prefix.clone());
+        agent.context_top_k = cfg.context_top_k;
+        agent.stop = cfg.ollama.stop.clone();
+        tracing::debug!("Provider capabilities: {:?}", agent.capabilities());
+        Ok(agent)
+    }
 
-        Ok(Self::with_provider(Box::new(ollama)))
+    fn build_provider(cfg: &AppConfig) -> Result<Box<dyn LlmProvider>> {
+        match (cfg.provider, &cfg.openai) {
+            (ProviderKind::OpenAi, Some(openai)) => {
+                tracing::info!(
+                    "Initializing agent with OpenAI model: {}",
+                    openai.model_name
+                );
+                Ok(Box::new(OpenAiClient::new(openai)?))
+            }
+            (ProviderKind::OpenAi, None) => Err(AppError::config(
+                "provider = \"openai\" needs an [openai] section",
+            )),
+            (ProviderKind::Ollama, _) => {
+                tracing::info!("Initializing agent with model: {}", cfg.ollama.model_name);
+
+                let ollama =
+                    OllamaClient::new(&cfg.ollama)?.with_log_truncation(cfg.truncation.clone());
+                Ok(Box::new(ollama))
+            }
+        }
     }
 
     pub fn with_provider(llm: Box<dyn LlmProvider>) -> Self {
-        Self { llm }
+        Self {
+            providers: vec![llm],
+            model_followers: Vec::new(),
+            max_history_messages: None,
+            system_prompt: None,
+            response_prefix: None,
+            needs_model_pull: false,
+            context: ContextStore::new(),
+            context_top_k: 3,
+            stop: Vec::new(),
+        }
+    }
+
+    fn primary(&self) -> &dyn LlmProvider {
+        self.providers[0].as_ref()
     }
 
+    /// Succeeds when any provider in the chain is reachable.
     pub async fn health_check(&self) -> Result<()> {
-        self.llm.health_check().await
+        let mut result = Ok(());
+        for provider in &self.providers {
+            result = provider.health_check().await;
+            match result {
+                Ok(()) => return Ok(()),
+                Err(ref e) => tracing::warn!("{} is unhealthy: {}", provider.describe(), e),
+            }
+        }
+        result
+    }
+
+    /// Picks the preferred model or checks the configured one is installed.
+    /// Call once the backend is reachable; sets `needs_model_pull` when a
+    /// missing model should be downloaded.
+    pub async fn resolve_model(&mut self, cfg: &AppConfig) -> Result<()> {
+        let installed = self.providers[0]
+            .resolve_model(&cfg.ollama.model_preferences)
+            .await?;
+        self.needs_model_pull = !installed && cfg.ollama.auto_pull;
+
+        let model = self.model_name().to_string();
+        for &index in &self.model_followers {
+            let fallback = &mut self.providers[index];
+            if fallback.model_name() != model {
+                fallback.set_model(&model)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The primary provider and where it is reached.
+    pub fn describe(&self) -> String {
+        self.primary().describe()
+    }
+
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.system_prompt.as_deref()
+    }
+
+    /// Replaces the system prompt for later turns; an empty prompt clears it.
+    /// It leads every request and is never trimmed with the history.
+    pub fn set_system_prompt(&mut self, prompt: Option<String>) {
+        self.system_prompt = prompt.filter(|prompt| !prompt.trim().is_empty());
+    }
+
+    pub fn response_prefix(&self) -> Option<&str> {
+        self.response_prefix.as_deref()
+    }
+
+    /// An empty prefix clears it.
+    pub fn set_response_prefix(&mut self, prefix: Option<String>) {
+        self.response_prefix = prefix.filter(|prefix| !prefix.is_empty());
+    }
+
+    pub fn needs_model_pull(&self) -> bool {
+        self.needs_model_pull
     }
 
-    pub async fn process(&self, text: &str) -> Result<ResponseStream> {
+    pub async fn pull_model(&self) -> Result<PullStream> {
+        self.primary().pull_model().await
+    }
+
+    pub fn model_name(&self) -> &str {
+        self.primary().model_name()
+    }
+
+    pub async fn context_window(&self) -> Result<Option<u64>> {
+        self.primary().context_window().await
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        self.primary().list_models().await
+    }
+
+    pub fn set_model(&mut self, model: &str) -> Result<()> {
+        for provider in &mut self.providers {
+            provider.set_model(model)?;
+        }
+        Ok(())
+    }
+
+    pub fn capabilities(&self) -> ProviderCapabilities {
+        self.primary().capabilities()
+    }
+
+    /// Fails with a clear error when the provider lacks `capability`.
+    pub fn require(&self, capability: Capability) -> Result<()> {
+        if self.capabilities().supports(capability) {
+            Ok(())
+        } else {
+            Err(AppError::Unsupported {
+                provider: self.primary().name().to_string(),
+                capability: capability.describe(),
+            })
+        }
+    }
+
+    /// Splits a text file into chunks and embeds them for retrieval, returning the chunk count.
+    pub async fn ingest(&mut self, path: &Path) -> Result<usize> {
+        self.require(Capability::Embeddings)?;
+        let text = std::fs::read_to_string(path)?;
+        let chunks = context::chunk_text(&text);
+        if chunks.is_empty() {
+            return Err(AppError::invalid_input(format!(
+                "{} has no text to ingest",
+                path.display()
+            )));
+        }
+
+        // Embed everything first so a failure leaves the store unchanged.
+        let mut embedded = Vec::with_capacity(chunks.len());
+        for text in chunks {
+            let embedding = self.primary().embed(&text).await?;
+            embedded.push(ContextChunk {
+                source: path.display().to_string(),
+                text,
+                embedding,
+            });
+        }
+
+        let count = embedded.len();
+        for chunk in embedded {
+            self.context.add(chunk);
+        }
+        tracing::info!(
+            "Ingested {} chunks from {} ({} total)",
+            count,
+            path.display(),
+            self.context.len()
+        );
+        Ok(count)
+    }
+
+    /// Ingested chunks relevant to `text`, formatted to precede the question.
+    async fn retrieve_context(&self, text: &str) -> Option<String> {
+        if self.context.is_empty() {
+            return None;
+        }
+        if let Err(e) = self.require(Capability::Embeddings) {
+            tracing::warn!("Skipping document context: {}", e);
+            return None;
+        }
+
+        let query = match self.primary().embed(text).await {
+            Ok(query) => query,
+            Err(e) => {
+                tracing::warn!("Skipping document context: {}", e);
+                return None;
+            }
+        };
+
+        let mut block = String::from("Use the following context to answer the question.\n");
+        for chunk in self.context.search(&query, self.context_top_k) {
+            block.push_str(&format!(
+                "\n--- context from {} ---\n{}\n",
+                chunk.source, chunk.text
+            ));
+        }
+        block.push_str("--- end of context ---\n\nQuestion: ");
+        Some(block)
+    }
+
+    pub async fn process(&self, session: &mut Session, text: &str) -> Result<ResponseStream> {
         self.validate_input(text)?;
         tracing::info!("Processing input: {} chars", text.len());
-        self.llm.chat(text).await
+
+        let context = self.retrieve_context(text).await;
+        let messages = self.prepare_messages(session, text, context);
+        let result = self.chat_with_fallback(&messages, None).await;
+        Self::rollback_on_error(session, result).map(|stream| self.shape_reply(stream))
+    }
+
+    /// Like `process`, overriding the provider's retry count for this request.
+    pub async fn process_with_retries(
+        &self,
+        session: &mut Session,
+        text: &str,
+        max_retries: u32,
+    ) -> Result<ResponseStream> {
+        self.validate_input(text)?;
+        tracing::info!(
+            "Processing input: {} chars (max_retries: {})",
+            text.len(),
+            max_retries
+        );
+
+        let context = self.retrieve_context(text).await;
+        let messages = self.prepare_messages(session, text, context);
+        let result = self.chat_with_fallback(&messages, Some(max_retries)).await;
+        Self::rollback_on_error(session, result).map(|stream| self.shape_reply(stream))
+    }
+
+    /// `n` alternative answers to `text`. The prompt is left out of the history
+    /// until one of them is picked.
+    pub async fn alternatives(
+        &self,
+        session: &mut Session,
+        text: &str,
+        n: u32,
+    ) -> Result<Vec<String>> {
+        self.validate_input(text)?;
+        tracing::info!("Requesting {} alternatives for {} chars", n, text.len());
+
+        let context = self.retrieve_context(text).await;
+        let messages = self.prepare_messages(session, text, context);
+        session.history.pop();
+        let answers = self.primary().alternatives(&messages, n).await?;
+        Ok(answers
+            .into_iter()
+            .map(|answer| match self.response_prefix {
+                Some(ref prefix) => llm::join_prefix(prefix, &answer),
+                None => answer,
+            })
+            .map(|answer| self.cut_at_stop(answer))
+            .collect())
+    }
+
+    fn cut_at_stop(&self, mut text: String) -> String {
+        if let Some(end) = self
+            .stop
+            .iter()
+            .filter_map(|stop| text.find(stop.as_str()))
+            .min()
+        {
+            text.truncate(end);
+        }
+        text
+    }
+
+    /// Puts the response prefix back in front of the reply and ends it at a stop sequence.
+    fn shape_reply(&self, mut stream: ResponseStream) -> ResponseStream {
+        if let Some(ref prefix) = self.response_prefix {
+            stream = llm::continue_prefix(stream, prefix.clone());
+        }
+        self.enforce_stop(stream)
+    }
+
+    fn enforce_stop(&self, stream: ResponseStream) -> ResponseStream {
+        if self.stop.is_empty() {
+            stream
+        } else {
+            llm::stop_at(stream, self.stop.clone())
+        }
+    }
+
+    /// Sends `messages` to each provider in turn until one accepts them. A provider
+    /// is skipped when it fails its health check or the request fails retryably.
+    async fn chat_with_fallback(
+        &self,
+        messages: &[Message],
+        max_retries: Option<u32>,
+    ) -> Result<ResponseStream> {
+        let has_fallbacks = self.providers.len() > 1;
+        let mut last_error = None;
+
+        for provider in &self.providers {
+            if has_fallbacks {
+                if let Err(e) = provider.health_check().await {
+                    tracing::warn!("Skipping {}: {}", provider.describe(), e);
+                    last_error = Some(e);
+                    continue;
+                }
+            }
+
+            let result = match max_retries {
+                Some(max_retries) => provider.chat_with_retries(messages, max_retries).await,
+                None => provider.chat(messages).await,
+            };
+            match result {
+                Ok(stream) => {
+                    tracing::info!("Request served by {}", provider.describe());
+                    return Ok(stream);
+                }
+                Err(e) if has_fallbacks && e.is_retryable() => {
+                    tracing::warn!("{} failed: {}", provider.describe(), e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| AppError::llm("no provider available")))
+    }
+
+    /// Appends the user turn, trims history to the configured cap and returns
+    /// the messages to send, led by the system prompt if any. Retrieved
+    /// `context` is prefixed to the sent turn only, not to the history.
+    fn prepare_messages(
+        &self,
+        session: &mut Session,
+        text: &str,
+        context: Option<String>,
+    ) -> Vec<Message> {
+        session.push_user(text);
+        session.stats.requests += 1;
+
+        let trimmed = match self.max_history_messages {
+            Some(max) => session.trim_history(max),
+            None => 0,
+        };
+
+        let mut messages = Vec::with_capacity(session.history.len() + 1);
+        if let Some(ref system_prompt) = self.system_prompt {
+            messages.push(Message::new(Role::System, system_prompt.as_str()));
+        }
+        messages.extend(session.history.iter().map(Message::without_meta));
+        if let (Some(context), Some(last)) = (context, messages.last_mut()) {
+            last.content = format!("{}{}", context, last.content);
+        }
+        if let Some(ref prefix) = self.response_prefix {
+            // Sent only; the reply stored in the history already starts with it.
+            messages.push(Message::new(Role::Assistant, prefix.as_str()));
+        }
+
+        Self::record_prompt_estimate(session, &messages, trimmed);
+        messages
+    }
+
+    fn record_prompt_estimate(session: &mut Session, messages: &[Message], trimmed: usize) {
+        let estimate = estimate_history_tokens(messages);
+        tracing::info!(
+            estimated_tokens = estimate,
+            messages = messages.len(),
+            trimmed_messages = trimmed,
+            "Prompt assembled"
+        );
+        session.stats.prompt_tokens_estimate = Some(estimate);
+        session.stats.trimmed_messages = trimmed as u64;
+    }
+
+    fn rollback_on_error(
+        session: &mut Session,
+        result: Result<ResponseStream>,
+    ) -> Result<ResponseStream> {
+        if result.is_err() {
+            session.history.pop();
+        }
+        result
     }
 
     fn validate_input(&self, text: &str) -> Result<()> {
@@ -51,3 +445,12 @@ impl Agent {
         Ok(())
     }
 }
+
+/// Rough token count: about four characters per token for English text.
+pub fn estimate_tokens(text: &str) -> u64 {
+    text.chars().count().div_ceil(4) as u64
+}
+
+pub fn estimate_history_tokens(history: &[Message]) -> u64 {
+    history.iter().map(|m| estimate_tokens(&m.content)).sum()
+}
@@ -0,0 +1,80 @@
+/// Interactive commands typed at the prompt, starting with `/`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Reset,
+    Retry,
+    Help,
+    Model(Option<String>),
+    Models,
+    Save(Option<String>),
+    Load(Option<String>),
+    Sessions,
+    Switch(Option<String>),
+    Ingest(Option<String>),
+    Set(Option<String>),
+    Pick(Option<String>),
+    Think(Option<String>),
+    System(Option<String>),
+    Prefix(Option<String>),
+    Translate(Option<String>),
+    Config,
+    Quit,
+    Unknown(String),
+}
+
+impl Command {
+    /// Returns `None` when `line` is ordinary chat input.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let rest = line.strip_prefix('/')?;
+
+        let (name, arg) = match rest.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim().to_string())),
+            None => (rest, None),
+        };
+        let arg = arg.filter(|a| !a.is_empty());
+
+        Some(match name.to_ascii_lowercase().as_str() {
+            "reset" => Self::Reset,
+            "retry" => Self::Retry,
+            "help" => Self::Help,
+            "model" => Self::Model(arg),
+            "models" => Self::Models,
+            "save" => Self::Save(arg),
+            "load" => Self::Load(arg),
+            "sessions" => Self::Sessions,
+            "switch" => Self::Switch(arg),
+            "ingest" => Self::Ingest(arg),
+            "set" => Self::Set(arg),
+            "pick" => Self::Pick(arg),
+            "think" => Self::Think(arg),
+            "system" => Self::System(arg),
+            "prefix" => Self::Prefix(arg),
+            "translate" => Self::Translate(arg),
+            "config" => Self::Config,
+            "quit" | "exit" => Self::Quit,
+            _ => Self::Unknown(name.to_string()),
+        })
+    }
+}
+
+pub const HELP: &str = "\
+Commands:
+  /reset            Clear the conversation history
+  /retry            Regenerate the last response
+  /model <name>     Switch to another model
+  /models           List installed models
+  /save [name]      Save the conversation, optionally under a name
+  /load <name>      Restore a named conversation
+  /sessions         List saved sessions (* marks this one)
+  /switch <name>    Save this session and continue in another, new or saved
+  /ingest <file>    Add a text file as context for later questions
+  /set [k [v]]      List, set or clear session variables
+  /pick <n>         Continue with alternative answer n (see completions)
+  /think on|off     Show or hide the reasoning of reasoning models
+  /system [text]    Show, set or clear (/system clear) the system prompt
+  /prefix [text]    Show, set or clear (/prefix clear) how replies start
+  /translate on|off Translate speech into English (voice mode)
+  /config           Show the settings in effect, including changes made here
+  /help             Show this help
+  /quit             Exit";
@@ -0,0 +1,97 @@
+/// Upper bound on the size of one ingested chunk, in characters.
+const CHUNK_CHARS: usize = 1000;
+
+/// A piece of an ingested document with its embedding.
+#[derive(Debug, Clone)]
+pub struct ContextChunk {
+    pub source: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// In-memory document chunks searched by embedding similarity.
+#[derive(Debug, Default)]
+pub struct ContextStore {
+    chunks: Vec<ContextChunk>,
+}
+
+impl ContextStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn add(&mut self, chunk: ContextChunk) {
+        self.chunks.push(chunk);
+    }
+
+    /// The `top_k` chunks most similar to `query_embedding`, best first.
+    pub fn search(&self, query_embedding: &[f32], top_k: usize) -> Vec<&ContextChunk> {
+        let mut scored: Vec<(f32, &ContextChunk)> = self
+            .chunks
+            .iter()
+            .map(|chunk| (cosine_similarity(query_embedding, &chunk.embedding), chunk))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        scored
+            .into_iter()
+            .take(top_k)
+            .map(|(_, chunk)| chunk)
+            .collect()
+    }
+}
+
+/// Cosine of the angle between two vectors; 0 when either is zero or their sizes differ.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (norm_a * norm_b)
+}
+
+/// Splits `text` into chunks of whole paragraphs where possible, each at most
+/// `CHUNK_CHARS` characters.
+pub fn chunk_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty()
+            && current.chars().count() + paragraph.chars().count() + 2 > CHUNK_CHARS
+        {
+            chunks.push(std::mem::take(&mut current));
+        }
+
+        if paragraph.chars().count() > CHUNK_CHARS {
+            let chars: Vec<char> = paragraph.chars().collect();
+            chunks.extend(chars.chunks(CHUNK_CHARS).map(|c| c.iter().collect()));
+            continue;
+        }
+
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
@@ -1,5 +1,12 @@
 pub mod agent;
+pub mod commands;
+pub mod context;
+pub mod reasoning;
 pub mod runtime;
+pub mod session;
+pub mod store;
+pub mod transcript;
 
 pub use agent::Agent;
-pub use runtime::run;
+pub use runtime::{run, run_batch, run_once, run_replay};
+pub use transcript::read_turns;
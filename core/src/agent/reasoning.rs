@@ -0,0 +1,128 @@
+const OPEN_TAG: &str = "<think>";
+const CLOSE_TAG: &str = "</think>";
+
+/// A piece of a streamed response, told apart by the `<think>` tags around it.
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Answer(String),
+    Reasoning(String),
+}
+
+/// Separates `<think>` blocks from the answer as chunks arrive. Text that could
+/// be the start of a tag is held back until the next chunk decides it.
+#[derive(Default)]
+pub struct ReasoningSplitter {
+    held: String,
+    in_reasoning: bool,
+    /// Whitespace between `</think>` and the answer is dropped.
+    after_reasoning: bool,
+}
+
+impl ReasoningSplitter {
+    pub fn push(&mut self, text: &str) -> Vec<Segment> {
+        self.held.push_str(text);
+
+        let mut segments = Vec::new();
+        loop {
+            let tag = if self.in_reasoning {
+                CLOSE_TAG
+            } else {
+                OPEN_TAG
+            };
+            match self.held.find(tag) {
+                Some(at) => {
+                    let before: String = self.held.drain(..at).collect();
+                    self.held.drain(..tag.len());
+                    self.add(&mut segments, before);
+                    self.in_reasoning = !self.in_reasoning;
+                    self.after_reasoning = !self.in_reasoning;
+                }
+                None => {
+                    let keep = partial_tag_len(&self.held, tag);
+                    let ready: String = self.held.drain(..self.held.len() - keep).collect();
+                    self.add(&mut segments, ready);
+                    return segments;
+                }
+            }
+        }
+    }
+
+    /// Releases held-back text once the response has ended.
+    pub fn finish(&mut self) -> Vec<Segment> {
+        let rest = std::mem::take(&mut self.held);
+        let mut segments = Vec::new();
+        self.add(&mut segments, rest);
+        segments
+    }
+
+    fn add(&mut self, segments: &mut Vec<Segment>, text: String) {
+        if self.in_reasoning {
+            if !text.is_empty() {
+                segments.push(Segment::Reasoning(text));
+            }
+            return;
+        }
+
+        let text = if self.after_reasoning {
+            let trimmed = text.trim_start();
+            self.after_reasoning = trimmed.is_empty();
+            trimmed.to_string()
+        } else {
+            text
+        };
+        if !text.is_empty() {
+            segments.push(Segment::Answer(text));
+        }
+    }
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of `tag`.
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&len| text.ends_with(&tag[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(chunks: &[&str]) -> Vec<Segment> {
+        let mut splitter = ReasoningSplitter::default();
+        let mut segments: Vec<Segment> = chunks
+            .iter()
+            .flat_map(|chunk| splitter.push(chunk))
+            .collect();
+        segments.extend(splitter.finish());
+        segments
+    }
+
+    #[test]
+    fn separates_reasoning_split_across_chunks() {
+        let segments = split(&["<thi", "nk>Let me see", ".</th", "ink>\n\nIt is 4."]);
+
+        assert_eq!(
+            segments,
+            [
+                Segment::Reasoning("Let me see".to_string()),
+                Segment::Reasoning(".".to_string()),
+                Segment::Answer("It is 4.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_answers_without_tags_through() {
+        let segments = split(&["1 < 2", " and <b>bold</b>"]);
+
+        let text: String = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Answer(text) => text.as_str(),
+                Segment::Reasoning(_) => panic!("no reasoning expected"),
+            })
+            .collect();
+        assert_eq!(text, "1 < 2 and <b>bold</b>");
+    }
+}
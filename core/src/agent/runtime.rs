@@ -1,32 +1,393 @@
-use crate::agent::agent::Agent;
-use crate::error::Result;
-use crate::io::{InputSource, OutputSink};
+use crate::agent::agent::{estimate_history_tokens, estimate_tokens, Agent};
+use crate::agent::commands::{self, Command};
+use crate::agent::reasoning::{ReasoningSplitter, Segment};
+use crate::agent::session::{Alternatives, Session};
+use crate::agent::store::SessionStore;
+use crate::agent::transcript::{self, RecordedTurn, TranscriptLog, TranscriptRecord};
+use crate::config::{
+    AppConfig, ContextOverflowPolicy, ModelSwitchPolicy, ProviderKind, ResendPolicy,
+    StreamGranularity, VoiceMode,
+};
+use crate::error::{AppError, Result};
+use crate::io::{BatchInput, InputSource, OutputSink, ResponseStats, Spinner};
+use crate::llm::{self, GenerationStats, ResponseStream};
+use async_trait::async_trait;
 use futures_util::StreamExt;
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::signal;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Appended to a partial response kept in history so the model knows it was cut off.
+const INTERRUPTED_MARKER: &str = "[response interrupted by the user]";
 
 pub async fn run(
     mut input: impl InputSource,
     mut output: impl OutputSink,
-    agent: Agent,
+    mut agent: Agent,
+    cfg: &AppConfig,
 ) -> Result<()> {
-    perform_health_check(&agent, &mut output).await?;
+    perform_health_check(&agent, &mut output, cfg.ollama.max_retries).await?;
+    agent.resolve_model(cfg).await?;
+    if agent.needs_model_pull() {
+        pull_model(&agent, &mut output).await?;
+    }
 
     output
         .emit("🤖 Agent ready. Type your message and press Enter. Ctrl+D or Ctrl+C to exit.")
         .await?;
     output.emit("").await?;
 
-    run_main_loop(&mut input, &mut output, &agent).await
+    let store = cfg.session_path.as_ref().map(SessionStore::new);
+    let mut session = match (store, &cfg.session) {
+        (Some(ref store), Some(name)) => store.open_named(name)?,
+        (Some(ref store), None) => store.load_or_default(),
+        (None, _) => Session::new(),
+    };
+    restore_model_override(&mut agent, &session, &mut output).await?;
+    restore_translate(&mut input, &session);
+    let result = run_main_loop(&mut input, &mut output, &mut agent, &mut session, cfg).await;
+
+    if let Err(e) = input.close().await {
+        tracing::warn!("Failed to close input on shutdown: {}", e);
+    }
+    if let Err(e) = output.flush().await {
+        tracing::warn!("Failed to flush output on shutdown: {}", e);
+    }
+
+    result
 }
 
-async fn perform_health_check(agent: &Agent, output: &mut impl OutputSink) -> Result<()> {
-    tracing::info!("Performing health check...");
+/// Runs every prompt from `input` with a fresh session, then reports which ones failed.
+pub async fn run_batch(
+    mut input: BatchInput,
+    mut output: impl OutputSink,
+    mut agent: Agent,
+    cfg: &AppConfig,
+) -> Result<()> {
+    perform_health_check(&agent, &mut output, cfg.ollama.max_retries).await?;
+    agent.resolve_model(cfg).await?;
+
+    let total = input.total();
+    let mut failures = Vec::new();
+    let mut skipped = 0;
+    let mut queued: VecDeque<String> = VecDeque::new();
+    let mut index = 0;
+
+    while let Some(text) = input.next().await? {
+        index += 1;
+        output
+            .emit(&format!("===== Prompt {}/{} =====", index, total))
+            .await?;
+        if text.is_empty() {
+            skipped += 1;
+            output.emit("(empty prompt, skipped)").await?;
+            output.emit("").await?;
+            continue;
+        }
+
+        let mut session = Session::new();
+        if let Err(e) = process_user_input(
+            &mut input,
+            &mut output,
+            &agent,
+            &mut session,
+            &mut queued,
+            &text,
+            None,
+            cfg,
+            &CancellationToken::new(),
+        )
+        .await
+        {
+            tracing::error!("Batch prompt {} failed: {}", index, e);
+            output.emit_app_error(&e).await?;
+            output.emit("").await?;
+            failures.push((index, e));
+        }
+    }
+    input.close().await?;
+
+    output.emit("===== Batch summary =====").await?;
+    output
+        .emit(&format!(
+            "{} succeeded, {} failed, {} skipped",
+            total - failures.len() - skipped,
+            failures.len(),
+            skipped
+        ))
+        .await?;
+    for (index, e) in &failures {
+        output
+            .emit_error(&format!("Prompt {} failed: {}", index, e))
+            .await?;
+    }
+    output.flush().await?;
+
+    Ok(())
+}
+
+/// Answers a single prompt with nothing but the response text, for scripts and pipelines.
+pub async fn run_once(
+    mut output: impl OutputSink,
+    mut agent: Agent,
+    prompt: &str,
+    cfg: &AppConfig,
+) -> Result<()> {
+    agent.resolve_model(cfg).await?;
+    let mut session = Session::new();
+    let mut stream = agent.process(&mut session, prompt).await?;
+
+    let mut completed = false;
+    let mut result = Ok(());
+    let mut reasoning = ReasoningSplitter::default();
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+        let answer =
+            show_reasoning(&mut output, reasoning.push(&chunk.text), cfg.show_reasoning).await?;
+        output.emit_chunk(&answer).await?;
+        if chunk.done {
+            completed = true;
+            break;
+        }
+    }
+    let answer = show_reasoning(&mut output, reasoning.finish(), cfg.show_reasoning).await?;
+    if !answer.is_empty() {
+        output.emit_chunk(&answer).await?;
+    }
+    output.finish_response().await?;
+
+    result?;
+    if !completed {
+        return Err(AppError::StreamEnded);
+    }
+    Ok(())
+}
+
+/// Sends each recorded prompt again with a fresh session and compares the
+/// normalized hash of the new response with the recorded one. Returns false
+/// when any response changed or failed.
+pub async fn run_replay(
+    turns: Vec<RecordedTurn>,
+    mut output: impl OutputSink,
+    mut agent: Agent,
+    cfg: &AppConfig,
+) -> Result<bool> {
+    perform_health_check(&agent, &mut output, cfg.ollama.max_retries).await?;
+    agent.resolve_model(cfg).await?;
+    // Replayed turns must not be appended to the transcript being replayed.
+    let cfg = AppConfig {
+        transcript_log: None,
+        ..cfg.clone()
+    };
+
+    let total = turns.len();
+    let (mut unchanged, mut changed, mut failed) = (0, Vec::new(), Vec::new());
+    for (i, turn) in turns.into_iter().enumerate() {
+        let index = i + 1;
+        output
+            .emit(&format!("===== Replay {}/{} =====", index, total))
+            .await?;
+
+        let mut session = Session::new();
+        if let Err(e) = process_user_input(
+            &mut NoInput,
+            &mut output,
+            &agent,
+            &mut session,
+            &mut VecDeque::new(),
+            &turn.prompt,
+            None,
+            &cfg,
+            &CancellationToken::new(),
+        )
+        .await
+        {
+            tracing::error!("Replay of turn {} failed: {}", index, e);
+            output.emit_app_error(&e).await?;
+            output.emit("").await?;
+            failed.push(index);
+            continue;
+        }
+
+        let response = session.history.last().map_or("", |m| m.content.as_str());
+        let (baseline, now) = (
+            transcript::response_hash(&turn.response),
+            transcript::response_hash(response),
+        );
+        if baseline == now {
+            unchanged += 1;
+            output.emit(&format!("[unchanged {:016x}]", now)).await?;
+        } else {
+            tracing::warn!("Replayed turn {} changed", index);
+            output
+                .emit(&format!(
+                    "[changed: was {:016x}, now {:016x}]",
+                    baseline, now
+                ))
+                .await?;
+            changed.push(index);
+        }
+        output.emit("").await?;
+    }
 
-    if let Err(e) = agent.health_check().await {
+    output.emit("===== Replay summary =====").await?;
+    output
+        .emit(&format!(
+            "{} unchanged, {} changed, {} failed",
+            unchanged,
+            changed.len(),
+            failed.len()
+        ))
+        .await?;
+    if !changed.is_empty() {
         output
-            .emit_error(&format!("Health check failed: {}", e))
+            .emit_error(&format!("Changed turns: {}", join_indexes(&changed)))
             .await?;
-        output.emit("Continuing anyway...").await?;
+    }
+    if !failed.is_empty() {
+        output
+            .emit_error(&format!("Failed turns: {}", join_indexes(&failed)))
+            .await?;
+    }
+    output.flush().await?;
+
+    Ok(changed.is_empty() && failed.is_empty())
+}
+
+/// Input for turns nobody types, such as replays; nothing arrives while they stream.
+struct NoInput;
+
+#[async_trait]
+impl InputSource for NoInput {
+    async fn next(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+fn join_indexes(indexes: &[usize]) -> String {
+    indexes
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks the backend up to `attempts` times, backing off in between, so a
+/// server that is still starting up gets a chance to come online.
+async fn perform_health_check(
+    agent: &Agent,
+    output: &mut impl OutputSink,
+    attempts: u32,
+) -> Result<()> {
+    tracing::info!("Performing health check...");
+
+    let attempts = attempts.max(1);
+    for attempt in 1..=attempts {
+        let Err(e) = agent.health_check().await else {
+            return Ok(());
+        };
+
+        if attempt < attempts {
+            let delay = llm::retry_delay(attempt);
+            tracing::warn!(
+                "Health check attempt {}/{} failed: {}",
+                attempt,
+                attempts,
+                e
+            );
+            output
+                .emit(&format!(
+                    "⏳ Backend not ready ({}), retrying in {} ms ({}/{})...",
+                    e,
+                    delay.as_millis(),
+                    attempt,
+                    attempts
+                ))
+                .await?;
+            tokio::time::sleep(delay).await;
+        } else {
+            output
+                .emit_error(&format!("Health check failed: {}", e))
+                .await?;
+            output.emit("Continuing anyway...").await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn pull_model(agent: &Agent, output: &mut impl OutputSink) -> Result<()> {
+    output
+        .emit("📦 Model not installed, downloading...")
+        .await?;
+
+    let mut progress = match agent.pull_model().await {
+        Ok(progress) => progress,
+        Err(e) => {
+            output
+                .emit_error(&format!("Model download failed: {}", e))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let redraw = output.is_terminal();
+    // Without a terminal, a line per status and per tenth of each layer.
+    let mut last_milestone = None;
+    while let Some(item) = progress.next().await {
+        match item {
+            Ok(progress) if progress.is_success() => {
+                if redraw {
+                    output.emit("").await?;
+                }
+                tracing::info!("Model download finished");
+                output.emit("✅ Model downloaded.").await?;
+                return Ok(());
+            }
+            Ok(progress) => {
+                if redraw {
+                    // Redraw the progress line in place.
+                    output
+                        .emit_chunk(&format!("\r\x1b[2K{}", progress.render()))
+                        .await?;
+                    output.flush().await?;
+                    continue;
+                }
+                let milestone = (
+                    progress.status.clone(),
+                    progress.percent().map(|percent| percent / 10 * 10),
+                );
+                if last_milestone.as_ref() != Some(&milestone) {
+                    tracing::info!(
+                        status = %progress.status,
+                        percent = milestone.1,
+                        "Model download progress"
+                    );
+                    output.emit(&progress.render()).await?;
+                    last_milestone = Some(milestone);
+                }
+            }
+            Err(e) => {
+                if redraw {
+                    output.emit("").await?;
+                }
+                output
+                    .emit_error(&format!("Model download failed: {}", e))
+                    .await?;
+                return Ok(());
+            }
+        }
     }
 
     Ok(())
@@ -35,36 +396,121 @@ async fn perform_health_check(agent: &Agent, output: &mut impl OutputSink) -> Re
 async fn run_main_loop(
     input: &mut impl InputSource,
     output: &mut impl OutputSink,
-    agent: &Agent,
+    agent: &mut Agent,
+    session: &mut Session,
+    cfg: &AppConfig,
 ) -> Result<()> {
-    let ctrl_c = async {
-        signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
-        tracing::info!("Received Ctrl+C signal");
-    };
+    let mut interrupts = Interrupts::listen();
 
-    tokio::pin!(ctrl_c);
+    // Lines waiting to be handled, including those typed while a response streamed.
+    let mut queued: VecDeque<String> = VecDeque::new();
+    let mut errors = ErrorStreak::default();
+    // Set when the prompt at the front of `queued` is a resend, which is not resent again.
+    let mut resending = false;
 
     loop {
+        if let Some(text) = queued.pop_front() {
+            let resent = std::mem::take(&mut resending);
+            let (text, max_retries) = match Command::parse(&text) {
+                None => (text, None),
+                Some(command) => {
+                    match handle_command(command, input, output, agent, session, cfg).await? {
+                        CommandOutcome::Done => continue,
+                        CommandOutcome::Quit => {
+                            output.emit("👋 Goodbye!").await?;
+                            break;
+                        }
+                        CommandOutcome::Resend(text) => (text, cfg.retry_command_max_retries),
+                    }
+                }
+            };
+
+            if session.alternatives.take().is_some() {
+                tracing::debug!("Discarding alternatives nobody picked");
+            }
+            let cancel = interrupts.begin_turn();
+            let result = if cfg.completions > 1 {
+                offer_alternatives(output, agent, session, &text, cfg.completions, &cancel).await
+            } else {
+                process_user_input(
+                    input,
+                    output,
+                    agent,
+                    session,
+                    &mut queued,
+                    &text,
+                    max_retries,
+                    cfg,
+                    &cancel,
+                )
+                .await
+            };
+            interrupts.end_turn();
+            if result.is_ok() {
+                autosave(session, cfg);
+            }
+            let reconnected = match result {
+                Err(ref e) if !resent && lost_connection(e) => agent.health_check().await.is_ok(),
+                _ => false,
+            };
+            if errors.record(result, output, cfg).await? {
+                break;
+            }
+            if reconnected && confirm_resend(input, output, cfg).await? {
+                queued.push_front(text);
+                resending = true;
+            }
+            continue;
+        }
+
+        if input.is_interactive() && !input.shows_prompt() && !cfg.prompt.is_empty() {
+            output.emit_chunk(&cfg.prompt).await?;
+            output.flush().await?;
+        }
+
+        let idle_exit_secs = cfg.idle_exit_secs;
+        let idle_timeout = async move {
+            match idle_exit_secs {
+                Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                None => std::future::pending().await,
+            }
+        };
+
         tokio::select! {
-            _ = &mut ctrl_c => {
+            Some(()) = interrupts.exit.recv() => {
                 output.emit("\n👋 Goodbye!").await?;
                 break;
             }
 
+            _ = idle_timeout => {
+                let secs = idle_exit_secs.unwrap_or_default();
+                tracing::info!("No input for {} seconds, exiting", secs);
+                output
+                    .emit(&format!("\n⏰ No input for {} seconds. Goodbye!", secs))
+                    .await?;
+                break;
+            }
+
             result = input.next() => {
                 match result {
-                    Ok(Some(text)) => {
-                        if let Err(e) = process_user_input(output, agent, &text).await {
-                            tracing::error!("Error processing input: {}", e);
-                            output.emit_error(&e.to_string()).await?;
-                            output.emit("").await?;
-                        }
+                    Ok(Some(text)) if text.trim().is_empty() => {
+                        tracing::trace!("Blank input, showing prompt again");
                     }
+                    Ok(Some(text)) => queued.push_back(text),
                     Ok(None) => {
                         tracing::info!("Reached EOF");
                         output.emit("\n👋 Goodbye!").await?;
                         break;
                     }
+                    Err(AppError::Cancelled) => {
+                        tracing::info!("Input cancelled with Ctrl+C");
+                        output.emit("\n👋 Goodbye!").await?;
+                        break;
+                    }
+                    Err(e @ AppError::SpeechRecognition(_)) => {
+                        tracing::warn!("Speech recognition error: {}", e);
+                        output.emit_app_error(&e).await?;
+                    }
                     Err(e) => {
                         tracing::error!("Input error: {}", e);
                         output.emit_error(&format!("Input error: {}", e)).await?;
@@ -80,31 +526,1758 @@ async fn run_main_loop(
     Ok(())
 }
 
-async fn process_user_input(output: &mut impl OutputSink, agent: &Agent, text: &str) -> Result<()> {
-    output.emit(&format!("You: {}", text)).await?;
-    output.emit("").await?;
-    output.emit("Assistant: ").await?;
+/// Switches to the model a restored session was using, as `/model` did when it was saved.
+async fn restore_model_override(
+    agent: &mut Agent,
+    session: &Session,
+    output: &mut impl OutputSink,
+) -> Result<()> {
+    if let Some(ref model) = session.model_override {
+        tracing::debug!("Restoring session model {}", model);
+        if let Err(e) = agent.set_model(model) {
+            output.emit_app_error(&e).await?;
+        }
+    }
+    Ok(())
+}
 
-    let mut stream = agent.process(text).await?;
-    let mut total_chars = 0;
+/// The settings in effect for /config: the file's values with the changes
+/// made by commands this session.
+fn effective_config(
+    agent: &Agent,
+    session: &Session,
+    cfg: &AppConfig,
+) -> Vec<(&'static str, String)> {
+    fn or(value: Option<impl ToString>, fallback: &str) -> String {
+        value.map_or_else(|| fallback.to_string(), |value| value.to_string())
+    }
+    let on_off = |on: bool| String::from(if on { "on" } else { "off" });
 
-    while let Some(chunk_result) = stream.next().await {
-        let chunk = chunk_result?;
+    let temperature = match (cfg.provider, &cfg.openai) {
+        (ProviderKind::OpenAi, Some(openai)) => openai.temperature,
+        _ => cfg.ollama.sampling.temperature,
+    };
+    let mut settings = vec![
+        ("provider", agent.describe()),
+        ("model", agent.model_name().to_string()),
+        ("temperature", or(temperature, "model default")),
+        ("system prompt", or(agent.system_prompt(), "none")),
+        ("response prefix", or(agent.response_prefix(), "none")),
+        ("history cap", or(cfg.ollama.max_history_messages, "none")),
+        ("completions", cfg.completions.to_string()),
+        ("session", or(session.name.as_deref(), "default")),
+        ("output", format!("{:?}", cfg.output_format).to_lowercase()),
+        (
+            "show reasoning",
+            on_off(session.show_reasoning.unwrap_or(cfg.show_reasoning)),
+        ),
+        (
+            "session file",
+            or(cfg.session_path.as_ref().map(|p| p.display()), "none"),
+        ),
+    ];
+    let Some(ref voice) = cfg.voice else {
+        settings.push(("voice", "off".to_string()));
+        return settings;
+    };
+    settings.extend([
+        ("voice model", voice.model_path.clone()),
+        ("voice language", voice.language.clone()),
+        (
+            "voice translate",
+            on_off(session.translate.unwrap_or(voice.translate)),
+        ),
+        (
+            "voice mode",
+            match voice.mode {
+                VoiceMode::Vad => "vad",
+                VoiceMode::PushToTalk => "push-to-talk",
+            }
+            .to_string(),
+        ),
+        ("speak responses", on_off(voice.speak_responses)),
+    ]);
+    settings
+}
 
-        if !chunk.text.is_empty() {
-            output.emit_chunk(&chunk.text).await?;
-            total_chars += chunk.text.len();
+/// Applies the /translate choice a restored session was using.
+fn restore_translate(input: &mut impl InputSource, session: &Session) {
+    if let Some(translate) = session.translate {
+        if !input.set_translate(translate) {
+            tracing::debug!("Session translate setting ignored by this input");
         }
+    }
+}
 
-        if chunk.done {
-            tracing::debug!("Stream completed, total chars: {}", total_chars);
-            break;
+/// What the main loop does after a slash command.
+enum CommandOutcome {
+    Done,
+    Quit,
+    /// Send this prompt again, e.g. for /retry.
+    Resend(String),
+}
+
+async fn handle_command(
+    command: Command,
+    input: &mut impl InputSource,
+    output: &mut impl OutputSink,
+    agent: &mut Agent,
+    session: &mut Session,
+    cfg: &AppConfig,
+) -> Result<CommandOutcome> {
+    tracing::debug!("Handling command: {:?}", command);
+
+    match command {
+        Command::Reset => {
+            session.history.clear();
+            output.emit("History cleared.").await?;
+        }
+        Command::Retry => match session.take_last_turn() {
+            // Resent ahead of anything typed meanwhile; no blank line before the answer.
+            Some(text) => return Ok(CommandOutcome::Resend(text)),
+            None => output.emit_error("Nothing to retry yet.").await?,
+        },
+        Command::Help => output.emit(commands::HELP).await?,
+        Command::Model(Some(name)) => {
+            switch_model(&name, input, output, agent, session, cfg).await?
+        }
+        Command::Model(None) => output.emit_error("Usage: /model <name>").await?,
+        Command::Models => match agent.list_models().await {
+            Ok(models) => {
+                output.emit("Installed models:").await?;
+                for model in models {
+                    output.emit(&format!("  {}", model)).await?;
+                }
+            }
+            Err(e) => output.emit_app_error(&e).await?,
+        },
+        Command::Save(name) => {
+            let saved = session_store(cfg).and_then(|store| match name {
+                Some(name) => store
+                    .save_named(&name, session)
+                    .map(|path| path.display().to_string()),
+                None => store
+                    .save_current(session)
+                    .map(|path| path.display().to_string()),
+            });
+            match saved {
+                Ok(location) => output.emit(&format!("Saved to {}.", location)).await?,
+                Err(e) => output.emit_error(&format!("Save failed: {}", e)).await?,
+            }
+        }
+        Command::Load(Some(name)) => {
+            match session_store(cfg).and_then(|store| store.load_named(&name)) {
+                Ok(loaded) => {
+                    // The conversation is copied in; it keeps saving under the current name.
+                    let current = session.name.take();
+                    *session = loaded;
+                    session.name = current;
+                    restore_model_override(agent, session, output).await?;
+                    restore_translate(input, session);
+                    output
+                        .emit(&format!(
+                            "Loaded session {} ({} messages).",
+                            name,
+                            session.history.len()
+                        ))
+                        .await?;
+                }
+                Err(e) => output.emit_error(&format!("Load failed: {}", e)).await?,
+            }
+        }
+        Command::Load(None) => output.emit_error("Usage: /load <name>").await?,
+        Command::Sessions => match session_store(cfg).and_then(|store| store.list()) {
+            Ok(names) if names.is_empty() => output.emit("No saved sessions.").await?,
+            Ok(names) => {
+                // An unnamed session lives in the default file, listed under its stem.
+                let current = session.name.clone().or_else(|| {
+                    cfg.session_path
+                        .as_ref()
+                        .and_then(|path| path.file_stem())
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                });
+                for name in names {
+                    let marker = if current.as_deref() == Some(name.as_str()) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    output.emit(&format!(" {} {}", marker, name)).await?;
+                }
+            }
+            Err(e) => {
+                output
+                    .emit_error(&format!("Listing sessions failed: {}", e))
+                    .await?
+            }
+        },
+        Command::Switch(Some(name)) => {
+            switch_session(&name, input, output, agent, session, cfg).await?
+        }
+        Command::Switch(None) => output.emit_error("Usage: /switch <name>").await?,
+        Command::Ingest(Some(path)) => match agent.ingest(Path::new(&path)).await {
+            Ok(chunks) => {
+                output
+                    .emit(&format!("Ingested {} chunks from {}.", chunks, path))
+                    .await?
+            }
+            Err(e) => output.emit_error(&format!("Ingest failed: {}", e)).await?,
+        },
+        Command::Ingest(None) => output.emit_error("Usage: /ingest <file>").await?,
+        Command::Set(None) if session.vars.is_empty() => {
+            output.emit("No session variables set.").await?
+        }
+        Command::Set(None) => {
+            let mut vars: Vec<_> = session.vars.iter().collect();
+            vars.sort();
+            for (key, value) in vars {
+                output.emit(&format!("  {} = {}", key, value)).await?;
+            }
+        }
+        Command::Set(Some(arg)) => match arg.split_once(char::is_whitespace) {
+            Some((key, value)) => {
+                session
+                    .vars
+                    .insert(key.to_string(), value.trim().to_string());
+                output.emit(&format!("Set {}.", key)).await?;
+            }
+            None => match session.vars.remove(&arg) {
+                Some(_) => output.emit(&format!("Cleared {}.", arg)).await?,
+                None => output.emit_error(&format!("{} is not set", arg)).await?,
+            },
+        },
+        Command::Pick(Some(arg)) => match session.alternatives.take() {
+            Some(alternatives) => match arg.parse::<usize>() {
+                Ok(n) if (1..=alternatives.answers.len()).contains(&n) => {
+                    let Alternatives {
+                        prompt,
+                        mut answers,
+                    } = alternatives;
+                    session.push_user(prompt);
+                    session.push_assistant(answers.swap_remove(n - 1));
+                    session.record_reply(agent.model_name(), None);
+                    autosave(session, cfg);
+                    output
+                        .emit(&format!("Continuing with alternative {}.", n))
+                        .await?;
+                }
+                _ => {
+                    output
+                        .emit_error(&format!(
+                            "Pick a number from 1 to {}",
+                            alternatives.answers.len()
+                        ))
+                        .await?;
+                    session.alternatives = Some(alternatives);
+                }
+            },
+            None => output.emit_error("No alternatives to pick from.").await?,
+        },
+        Command::Pick(None) => output.emit_error("Usage: /pick <n>").await?,
+        Command::Think(arg) => match arg.map(|arg| arg.to_ascii_lowercase()).as_deref() {
+            Some("on") => {
+                session.show_reasoning = Some(true);
+                output.emit("Reasoning will be shown.").await?;
+            }
+            Some("off") => {
+                session.show_reasoning = Some(false);
+                output.emit("Reasoning will be hidden.").await?;
+            }
+            _ => output.emit_error("Usage: /think on|off").await?,
+        },
+        Command::Translate(arg) => {
+            let translate = match arg.map(|arg| arg.to_ascii_lowercase()).as_deref() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => {
+                    output.emit_error("Usage: /translate on|off").await?;
+                    return Ok(CommandOutcome::Done);
+                }
+            };
+            if input.set_translate(translate) {
+                session.translate = Some(translate);
+                autosave(session, cfg);
+                output
+                    .emit(if translate {
+                        "Speech will be translated into English."
+                    } else {
+                        "Speech will be transcribed as spoken."
+                    })
+                    .await?;
+            } else {
+                output
+                    .emit_error("/translate only applies to voice input")
+                    .await?;
+            }
+        }
+        Command::Config => {
+            for (name, value) in effective_config(agent, session, cfg) {
+                output.emit(&format!("  {:<16} {}", name, value)).await?;
+            }
+        }
+        Command::System(None) => match agent.system_prompt() {
+            Some(prompt) => output.emit(&format!("System prompt: {}", prompt)).await?,
+            None => output.emit("No system prompt set.").await?,
+        },
+        Command::System(Some(arg)) if arg.eq_ignore_ascii_case("clear") => {
+            agent.set_system_prompt(None);
+            output.emit("System prompt cleared.").await?;
+        }
+        Command::System(Some(prompt)) => {
+            output
+                .emit(&format!("System prompt is now: {}", prompt))
+                .await?;
+            agent.set_system_prompt(Some(prompt));
+        }
+        Command::Prefix(None) => match agent.response_prefix() {
+            Some(prefix) => {
+                output
+                    .emit(&format!("Replies start with: {}", prefix))
+                    .await?
+            }
+            None => output.emit("No response prefix set.").await?,
+        },
+        Command::Prefix(Some(arg)) if arg.eq_ignore_ascii_case("clear") => {
+            agent.set_response_prefix(None);
+            output.emit("Response prefix cleared.").await?;
+        }
+        Command::Prefix(Some(prefix)) => {
+            output
+                .emit(&format!("Replies will start with: {}", prefix))
+                .await?;
+            agent.set_response_prefix(Some(prefix));
+        }
+        Command::Quit => return Ok(CommandOutcome::Quit),
+        Command::Unknown(name) => {
+            output
+                .emit_error(&format!("Unknown command /{} (try /help)", name))
+                .await?;
         }
     }
+    output.emit("").await?;
 
-    output.flush().await?;
-    output.emit("\n").await?;
+    Ok(CommandOutcome::Done)
+}
+
+/// Sends `text` and applies the display pacing from the config to the reply.
+async fn open_stream(
+    agent: &Agent,
+    session: &mut Session,
+    text: &str,
+    max_retries: Option<u32>,
+    cfg: &AppConfig,
+) -> Result<ResponseStream> {
+    let mut stream = match max_retries {
+        Some(max_retries) => {
+            agent
+                .process_with_retries(session, text, max_retries)
+                .await?
+        }
+        None => agent.process(session, text).await?,
+    };
+    if let Some(window_ms) = cfg.coalesce_window_ms {
+        stream = llm::coalesce(stream, Duration::from_millis(window_ms));
+    }
+    if let Some(tokens_per_sec) = cfg.max_display_tokens_per_sec {
+        stream = llm::pace(stream, tokens_per_sec);
+    }
+    Ok(stream)
+}
+
+/// The next item of `stream`, or `None` if nothing arrives within `stall_timeout`.
+async fn next_before_stall(
+    stream: &mut ResponseStream,
+    stall_timeout: Option<Duration>,
+) -> Option<Option<Result<llm::StreamChunk>>> {
+    match stall_timeout {
+        Some(limit) => tokio::time::timeout(limit, stream.next()).await.ok(),
+        None => Some(stream.next().await),
+    }
+}
+
+/// Shows the reasoning among `segments` when `shown`, otherwise drops it, and
+/// returns the answer text for the caller to emit.
+async fn show_reasoning(
+    output: &mut impl OutputSink,
+    segments: Vec<Segment>,
+    shown: bool,
+) -> Result<String> {
+    let mut answer = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Answer(text) => answer.push_str(&text),
+            Segment::Reasoning(text) if shown => output.emit_reasoning(&text).await?,
+            Segment::Reasoning(_) => {}
+        }
+    }
+    Ok(answer)
+}
+
+/// Shows `n` numbered answers to `text` and keeps them for /pick.
+async fn offer_alternatives(
+    output: &mut impl OutputSink,
+    agent: &Agent,
+    session: &mut Session,
+    text: &str,
+    n: u32,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    output.begin_turn(text, agent.model_name()).await?;
+
+    let show_spinner = output.is_terminal() && std::io::stderr().is_terminal();
+    let spinner = show_spinner.then(Spinner::start);
+    let result = tokio::select! {
+        result = agent.alternatives(session, text, n) => result,
+        _ = cancel.cancelled() => Err(AppError::Cancelled),
+    };
+    if let Some(spinner) = spinner {
+        spinner.stop().await;
+    }
+    let answers = match result {
+        Err(AppError::Cancelled) => {
+            output.emit("[cancelled]").await?;
+            return output.emit("").await;
+        }
+        result => result?,
+    };
+
+    for (i, answer) in answers.iter().enumerate() {
+        output.emit(&format!("Alternative {}:", i + 1)).await?;
+        output.emit(answer.trim_end()).await?;
+        output.emit("").await?;
+    }
+    output
+        .emit(&format!(
+            "Use /pick <1-{}> to continue with one of them.",
+            answers.len()
+        ))
+        .await?;
     output.emit("").await?;
 
+    session.alternatives = Some(Alternatives {
+        prompt: text.to_string(),
+        answers,
+    });
     Ok(())
 }
+
+/// Switches models for /model and applies `cfg.model_switch` and
+/// `cfg.context_overflow` to the history.
+async fn switch_model(
+    name: &str,
+    input: &mut impl InputSource,
+    output: &mut impl OutputSink,
+    agent: &mut Agent,
+    session: &mut Session,
+    cfg: &AppConfig,
+) -> Result<()> {
+    let previous = agent.model_name().to_string();
+    if let Err(e) = agent.set_model(name) {
+        return output.emit_app_error(&e).await;
+    }
+
+    let overflow = if session.history.is_empty() || cfg.model_switch == ModelSwitchPolicy::Clear {
+        None
+    } else {
+        history_overflow(agent, session).await
+    };
+    if let (Some((estimate, window)), ContextOverflowPolicy::Block) =
+        (overflow, cfg.context_overflow)
+    {
+        agent.set_model(&previous)?;
+        return output
+            .emit_error(&format!(
+                "Staying on {}: the history is about {} tokens, more than the {} tokens {} can take. /reset clears it.",
+                previous, estimate, window, name
+            ))
+            .await;
+    }
+    session.model_override = Some(name.to_string());
+    output.emit(&format!("Switched to model {}.", name)).await?;
+
+    if session.history.is_empty() {
+        return Ok(());
+    }
+    let clear = match cfg.model_switch {
+        ModelSwitchPolicy::Keep => false,
+        ModelSwitchPolicy::Clear => true,
+        ModelSwitchPolicy::Prompt if input.is_interactive() => {
+            output
+                .emit_chunk("Clear the conversation history for the new model? [y/N] ")
+                .await?;
+            output.flush().await?;
+            let answer = input.next_while_streaming().await?.unwrap_or_default();
+            matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+        }
+        ModelSwitchPolicy::Prompt => false,
+    };
+    if clear {
+        session.history.clear();
+        return output.emit("History cleared.").await;
+    }
+
+    let Some((estimate, window)) = overflow else {
+        return Ok(());
+    };
+    match cfg.context_overflow {
+        ContextOverflowPolicy::Compact => {
+            let dropped = compact_history(session, window.saturating_sub(estimate_system(agent)));
+            output
+                .emit(&format!(
+                    "Dropped the {} oldest messages: the history was about {} tokens, more than the {} tokens {} can take.",
+                    dropped, estimate, window, name
+                ))
+                .await
+        }
+        ContextOverflowPolicy::Warn | ContextOverflowPolicy::Block => {
+            output
+                .emit_error(&format!(
+                    "The history is about {} tokens, more than the {} tokens {} can take; older messages may be cut off. /reset clears it.",
+                    estimate, window, name
+                ))
+                .await
+        }
+    }
+}
+
+/// The history's token estimate and the current model's context window, when
+/// the history (with the system prompt) does not fit.
+async fn history_overflow(agent: &Agent, session: &Session) -> Option<(u64, u64)> {
+    let window = context_window(agent).await?;
+    let estimate = estimate_system(agent) + estimate_history_tokens(&session.history);
+    (estimate > window).then_some((estimate, window))
+}
+
+fn estimate_system(agent: &Agent) -> u64 {
+    agent.system_prompt().map_or(0, estimate_tokens)
+}
+
+/// Drops the oldest messages until the history's estimate fits `budget`,
+/// returning how many were removed.
+fn compact_history(session: &mut Session, budget: u64) -> usize {
+    let mut total = estimate_history_tokens(&session.history);
+    let excess = session
+        .history
+        .iter()
+        .take_while(|message| {
+            let over = total > budget;
+            total -= estimate_tokens(&message.content);
+            over
+        })
+        .count();
+    session.trim_history(session.history.len() - excess)
+}
+
+/// Errors after which the backend may be reachable again, so the prompt could be resent.
+fn lost_connection(error: &AppError) -> bool {
+    error.is_retryable() || matches!(error, AppError::StreamEnded)
+}
+
+/// Applies `resend_after_reconnect` once the backend answers again after a lost connection.
+async fn confirm_resend(
+    input: &mut impl InputSource,
+    output: &mut impl OutputSink,
+    cfg: &AppConfig,
+) -> Result<bool> {
+    let policy = cfg
+        .resend_after_reconnect
+        .unwrap_or(if input.is_interactive() {
+            ResendPolicy::Ask
+        } else {
+            ResendPolicy::Never
+        });
+    let resend = match policy {
+        ResendPolicy::Auto => true,
+        ResendPolicy::Ask if input.is_interactive() => {
+            output
+                .emit_chunk("Reconnected. Send the prompt again? [y/N] ")
+                .await?;
+            output.flush().await?;
+            let answer = input.next_while_streaming().await?.unwrap_or_default();
+            matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+        }
+        ResendPolicy::Ask | ResendPolicy::Never => false,
+    };
+    tracing::info!(resend, "Backend reachable again after a lost connection");
+    if resend {
+        output
+            .emit("Reconnected; sending the prompt again.")
+            .await?;
+    } else {
+        output
+            .emit("Reconnected; the prompt was not sent again.")
+            .await?;
+    }
+    Ok(resend)
+}
+
+/// The current model's context window, or `None` when it cannot be determined.
+async fn context_window(agent: &Agent) -> Option<u64> {
+    match agent.context_window().await {
+        Ok(window) => window,
+        Err(e) => {
+            tracing::warn!(
+                "Could not get the context window of {}: {}",
+                agent.model_name(),
+                e
+            );
+            None
+        }
+    }
+}
+
+fn session_store(cfg: &AppConfig) -> Result<SessionStore> {
+    cfg.session_path
+        .as_ref()
+        .map(SessionStore::new)
+        .ok_or_else(|| AppError::config("session_path is not set"))
+}
+
+fn autosave(session: &Session, cfg: &AppConfig) {
+    if let Some(ref path) = cfg.session_path {
+        if let Err(e) = SessionStore::new(path).save_current(session) {
+            tracing::warn!("Failed to save session to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Saves the current session and continues in the one named `name`, starting it if new.
+async fn switch_session(
+    name: &str,
+    input: &mut impl InputSource,
+    output: &mut impl OutputSink,
+    agent: &mut Agent,
+    session: &mut Session,
+    cfg: &AppConfig,
+) -> Result<()> {
+    let store = match session_store(cfg) {
+        Ok(store) => store,
+        Err(e) => return output.emit_error(&format!("Switch failed: {}", e)).await,
+    };
+    if session.name.as_deref() == Some(name) {
+        return output.emit(&format!("Already in session {}.", name)).await;
+    }
+    let next = match store.open_named(name) {
+        Ok(next) => next,
+        Err(e) => return output.emit_error(&format!("Switch failed: {}", e)).await,
+    };
+    if let Err(e) = store.save_current(session) {
+        return output.emit_error(&format!("Switch failed: {}", e)).await;
+    }
+
+    *session = next;
+    restore_model_override(agent, session, output).await?;
+    restore_translate(input, session);
+    output
+        .emit(&format!(
+            "Switched to session {} ({} messages).",
+            name,
+            session.history.len()
+        ))
+        .await
+}
+
+/// Routes Ctrl+C: it cancels the response being streamed, or asks to exit when idle.
+struct Interrupts {
+    current: Arc<Mutex<Option<CancellationToken>>>,
+    exit: mpsc::UnboundedReceiver<()>,
+}
+
+impl Interrupts {
+    fn listen() -> Self {
+        let current: Arc<Mutex<Option<CancellationToken>>> = Arc::new(Mutex::new(None));
+        let (exit_tx, exit) = mpsc::unbounded_channel();
+
+        let turn = current.clone();
+        tokio::spawn(async move {
+            while signal::ctrl_c().await.is_ok() {
+                let token = turn.lock().unwrap_or_else(|e| e.into_inner()).take();
+                match token {
+                    Some(token) => {
+                        tracing::info!("Received Ctrl+C, cancelling current response");
+                        token.cancel();
+                    }
+                    None => {
+                        tracing::info!("Received Ctrl+C signal");
+                        if exit_tx.send(()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { current, exit }
+    }
+
+    fn begin_turn(&self) -> CancellationToken {
+        let token = CancellationToken::new();
+        *self.current.lock().unwrap_or_else(|e| e.into_inner()) = Some(token.clone());
+        token
+    }
+
+    fn end_turn(&self) {
+        self.current
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+    }
+}
+
+/// Counts consecutive turns that failed with the same error.
+#[derive(Default)]
+struct ErrorStreak {
+    last: Option<String>,
+    count: u32,
+}
+
+impl ErrorStreak {
+    /// Reports a turn's error, escalating on repeats; returns true when the session should end.
+    async fn record(
+        &mut self,
+        result: Result<()>,
+        output: &mut impl OutputSink,
+        cfg: &AppConfig,
+    ) -> Result<bool> {
+        let e = match result {
+            Ok(()) => {
+                self.last = None;
+                self.count = 0;
+                return Ok(false);
+            }
+            Err(e) => e,
+        };
+
+        tracing::error!("Error processing input: {}", e);
+        output.emit_app_error(&e).await?;
+
+        let message = e.to_string();
+        if self.last.as_deref() == Some(message.as_str()) {
+            self.count += 1;
+        } else {
+            self.last = Some(message);
+            self.count = 1;
+        }
+
+        if e.is_fatal() {
+            tracing::error!("Unrecoverable error, ending session");
+            return Ok(true);
+        }
+
+        let repeated = cfg.repeated_error_threshold == Some(self.count);
+        if repeated {
+            tracing::warn!("Same error {} times in a row", self.count);
+            output
+                .emit_error(&format!(
+                    "The last {} requests failed the same way. {}",
+                    self.count,
+                    repeated_error_hint(&e, cfg)
+                ))
+                .await?;
+            if !cfg.exit_on_repeated_errors {
+                output
+                    .emit("Press Ctrl+D or Ctrl+C to exit, or keep typing to try again.")
+                    .await?;
+            }
+        }
+        output.emit("").await?;
+
+        Ok(repeated && cfg.exit_on_repeated_errors)
+    }
+}
+
+fn repeated_error_hint(e: &AppError, cfg: &AppConfig) -> String {
+    match e {
+        AppError::Http(_) | AppError::ServiceUnavailable(_) | AppError::Timeout { .. } => format!(
+            "Check that Ollama is running and reachable at {} (try `ollama list`).",
+            cfg.ollama.base_url
+        ),
+        AppError::Llm(msg) if msg.contains("not found") => format!(
+            "Model {} may not be installed; run `ollama pull {}` or change ollama.model_name.",
+            cfg.ollama.model_name, cfg.ollama.model_name
+        ),
+        AppError::RetryBudgetExhausted { .. } => {
+            "The backend looks degraded; wait for the retry window to pass or restart Ollama."
+                .to_string()
+        }
+        _ => "Check the config file and the logs (RUST_LOG=debug) for details.".to_string(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_user_input(
+    input: &mut impl InputSource,
+    output: &mut impl OutputSink,
+    agent: &Agent,
+    session: &mut Session,
+    queued: &mut VecDeque<String>,
+    text: &str,
+    max_retries: Option<u32>,
+    cfg: &AppConfig,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    output.begin_turn(text, agent.model_name()).await?;
+    output.emit("Assistant: ").await?;
+
+    let started = Instant::now();
+    // The spinner draws on stderr, so both streams must be terminals.
+    let show_spinner = output.is_terminal() && std::io::stderr().is_terminal();
+    let mut spinner = show_spinner.then(Spinner::start);
+    let mut stream = match open_stream(agent, session, text, max_retries, cfg).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            if let Some(spinner) = spinner.take() {
+                spinner.stop().await;
+            }
+            output.finish_response().await?;
+            return Err(e);
+        }
+    };
+    if cfg.show_prompt_estimate {
+        if let Some(estimate) = session.stats.prompt_tokens_estimate {
+            if let Some(spinner) = spinner.take() {
+                spinner.stop().await;
+            }
+            let notice = match session.stats.trimmed_messages {
+                0 => format!("[prompt: ~{} tokens]", estimate),
+                1 => format!("[prompt: ~{} tokens, 1 older message trimmed]", estimate),
+                trimmed => format!(
+                    "[prompt: ~{} tokens, {} older messages trimmed]",
+                    estimate, trimmed
+                ),
+            };
+            output.emit(&notice).await?;
+            spinner = show_spinner.then(Spinner::start);
+        }
+    }
+    let mut response = String::new();
+    let mut pending_line = String::new();
+    let mut completed = false;
+    // Set to the notice to print when the user stops the response early.
+    let mut stopped = None;
+    let mut failed = None;
+    let mut time_to_first_token = None;
+    let mut generation_stats = None;
+    let mut watch_input = input.is_interactive() && !cfg.abort_keyword.is_empty();
+    let mut reasoning = ReasoningSplitter::default();
+    let reasoning_shown = session.show_reasoning.unwrap_or(cfg.show_reasoning);
+    // The stall timer only runs once the current attempt has sent something;
+    // the wait for the first token is left to the provider's own timeouts.
+    let mut receiving = false;
+    let mut stall_restarts = 0;
+
+    loop {
+        let stall_timeout =
+            (cfg.restart_on_stall && receiving && stall_restarts < cfg.max_stall_restarts)
+                .then(|| Duration::from_secs(cfg.stall_timeout_secs));
+        let next_chunk = tokio::select! {
+            _ = cancel.cancelled() => {
+                stopped = Some("[cancelled]");
+                break;
+            }
+            item = next_before_stall(&mut stream, stall_timeout) => match item {
+                Some(item) => item,
+                None => {
+                    stall_restarts += 1;
+                    tracing::warn!(
+                        "No output for {} s after {} bytes, restarting the response ({}/{})",
+                        cfg.stall_timeout_secs,
+                        response.len(),
+                        stall_restarts,
+                        cfg.max_stall_restarts
+                    );
+                    if matches!(cfg.stream_granularity, StreamGranularity::Token)
+                        && !response.ends_with('\n')
+                    {
+                        output.emit_chunk("\n").await?;
+                    }
+                    pending_line.clear();
+                    output.emit("[stalled, restarting the response]").await?;
+                    // The retried request pushes the prompt again.
+                    session.history.pop();
+                    stream = match open_stream(agent, session, text, max_retries, cfg).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            output.finish_response().await?;
+                            return Err(e);
+                        }
+                    };
+                    response.clear();
+                    reasoning = ReasoningSplitter::default();
+                    receiving = false;
+                    continue;
+                }
+            },
+            line = input.next_while_streaming(), if watch_input => {
+                match line {
+                    Ok(Some(line)) if line.trim().eq_ignore_ascii_case(&cfg.abort_keyword) => {
+                        tracing::info!("Abort keyword received, cancelling stream");
+                        stopped = Some("[aborted]");
+                        break;
+                    }
+                    Ok(Some(line)) => {
+                        if !line.trim().is_empty() {
+                            queued.push_back(line);
+                        }
+                    }
+                    Ok(None) => watch_input = false,
+                    // Ctrl+C read by a line editor rather than delivered as a signal.
+                    Err(AppError::Cancelled) => {
+                        stopped = Some("[cancelled]");
+                        break;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Input error while streaming: {}", e);
+                        watch_input = false;
+                    }
+                }
+                continue;
+            }
+        };
+
+        let Some(chunk_result) = next_chunk else {
+            break;
+        };
+        let chunk = match chunk_result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                failed = Some(e);
+                break;
+            }
+        };
+
+        if response.len() + chunk.text.len() > cfg.max_response_bytes {
+            tracing::warn!(
+                "Response exceeded {} bytes, stopping stream",
+                cfg.max_response_bytes
+            );
+            if let Some(spinner) = spinner.take() {
+                spinner.stop().await;
+            }
+            // Keep what fits, cut back to a char boundary, along with any unfinished line.
+            let mut allowed = cfg.max_response_bytes - response.len();
+            while !chunk.text.is_char_boundary(allowed) {
+                allowed -= 1;
+            }
+            let prefix = &chunk.text[..allowed];
+            pending_line.push_str(prefix);
+            output
+                .emit_chunk(&std::mem::take(&mut pending_line))
+                .await?;
+            response.push_str(prefix);
+            output.emit_chunk(&cfg.truncation.marker).await?;
+            output.flush().await?;
+            output
+                .emit_error(&format!(
+                    "Response truncated: exceeded {} bytes",
+                    cfg.max_response_bytes
+                ))
+                .await?;
+            completed = true;
+            break;
+        }
+
+        if !chunk.text.is_empty() {
+            receiving = true;
+            if let Some(spinner) = spinner.take() {
+                spinner.stop().await;
+            }
+            if time_to_first_token.is_none() {
+                let elapsed = started.elapsed();
+                tracing::debug!("First token after {} ms", elapsed.as_millis());
+                time_to_first_token = Some(elapsed);
+            }
+            // Reasoning is never part of the answer kept in history.
+            let answer =
+                show_reasoning(output, reasoning.push(&chunk.text), reasoning_shown).await?;
+            match cfg.stream_granularity {
+                StreamGranularity::Token => output.emit_chunk(&answer).await?,
+                StreamGranularity::Line => {
+                    pending_line.push_str(&answer);
+                    emit_complete_lines(output, &mut pending_line).await?;
+                }
+            }
+            response.push_str(&answer);
+        }
+
+        if chunk.stats.is_some() {
+            generation_stats = chunk.stats;
+        }
+
+        if chunk.done {
+            tracing::debug!("Stream completed, total bytes: {}", response.len());
+            completed = true;
+            break;
+        }
+    }
+
+    if let Some(spinner) = spinner.take() {
+        spinner.stop().await;
+    }
+    let answer = show_reasoning(output, reasoning.finish(), reasoning_shown).await?;
+    pending_line.push_str(&answer);
+    response.push_str(&answer);
+    if !pending_line.is_empty() {
+        output.emit_chunk(&pending_line).await?;
+    }
+
+    let time_to_first_token_ms = time_to_first_token.map(|d| d.as_millis() as u64);
+    let duration_ms = started.elapsed().as_millis() as u64;
+    session.stats.time_to_first_token_ms = time_to_first_token_ms;
+    tracing::info!(
+        time_to_first_token_ms,
+        total_ms = duration_ms,
+        "Response timing"
+    );
+
+    // Every turn ends the response, so sinks reset their state and JSON gets its done record.
+    output.record_stats(ResponseStats {
+        time_to_first_token_ms,
+        duration_ms: Some(duration_ms),
+        prompt_tokens: generation_stats.and_then(|stats| stats.prompt_tokens),
+        completion_tokens: generation_stats.and_then(|stats| stats.completion_tokens),
+    });
+    output.finish_response().await?;
+
+    if let Some(e) = failed {
+        // Don't leave an unanswered user turn in the history.
+        session.history.pop();
+        return Err(e);
+    }
+
+    if let Some(notice) = stopped {
+        output.emit(notice).await?;
+        output.emit("").await?;
+        if cfg.keep_cancelled_in_history && !response.is_empty() {
+            tracing::debug!("Keeping {} bytes of aborted response", response.len());
+            session.push_assistant(format!("{}\n{}", response, INTERRUPTED_MARKER));
+            session.record_reply(agent.model_name(), None);
+        } else {
+            session.history.pop();
+        }
+        return Ok(());
+    }
+
+    if !completed {
+        tracing::warn!(
+            "Stream ended without completion after {} bytes",
+            response.len()
+        );
+        session.history.pop();
+        return Err(AppError::StreamEnded);
+    }
+
+    if let Some(stats) = generation_stats {
+        record_generation_stats(session, &stats);
+        if cfg.show_stats {
+            output
+                .emit(&format_generation_stats(&stats, time_to_first_token_ms))
+                .await?;
+        }
+    }
+    if let Some(ref path) = cfg.transcript_log {
+        let record = TranscriptRecord::new(
+            agent.model_name(),
+            text,
+            &response,
+            generation_stats.as_ref(),
+        );
+        if let Err(e) = TranscriptLog::new(path).append(&record) {
+            tracing::warn!("Failed to write transcript to {}: {}", path.display(), e);
+        }
+    }
+
+    session.push_assistant(response);
+    session.record_reply(agent.model_name(), generation_stats.as_ref());
+
+    Ok(())
+}
+
+fn record_generation_stats(session: &mut Session, stats: &GenerationStats) {
+    session.stats.prompt_tokens += stats.prompt_tokens.unwrap_or_default();
+    session.stats.completion_tokens += stats.completion_tokens.unwrap_or_default();
+    tracing::info!(
+        prompt_tokens = stats.prompt_tokens,
+        completion_tokens = stats.completion_tokens,
+        tokens_per_sec = stats.tokens_per_sec(),
+        "Generation stats"
+    );
+}
+
+fn format_generation_stats(stats: &GenerationStats, time_to_first_token_ms: Option<u64>) -> String {
+    let mut parts = Vec::new();
+    if let Some(tokens) = stats.completion_tokens {
+        parts.push(format!("{} tokens", tokens));
+    }
+    if let Some(rate) = stats.tokens_per_sec() {
+        parts.push(format!("{:.1} tokens/s", rate));
+    }
+    if let Some(tokens) = stats.prompt_tokens {
+        parts.push(format!("prompt {} tokens", tokens));
+    }
+    if let Some(ms) = time_to_first_token_ms {
+        parts.push(format!("first token {} ms", ms));
+    }
+    format!("[{}]", parts.join(", "))
+}
+
+async fn emit_complete_lines(output: &mut impl OutputSink, pending: &mut String) -> Result<()> {
+    if let Some(pos) = pending.rfind('\n') {
+        let rest = pending.split_off(pos + 1);
+        output.emit_chunk(pending).await?;
+        output.flush().await?;
+        *pending = rest;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{LlmProvider, Message, PullProgress, PullStream, ResponseStream, StreamChunk};
+
+    /// Streams the queued items once, then ends the stream.
+    struct StubProvider {
+        items: Mutex<Vec<Result<StreamChunk>>>,
+    }
+
+    impl StubProvider {
+        fn new(items: Vec<Result<StreamChunk>>) -> Self {
+            Self {
+                items: Mutex::new(items),
+            }
+        }
+    }
+
+    fn chunk(text: &str, done: bool) -> Result<StreamChunk> {
+        Ok(StreamChunk {
+            text: text.to_string(),
+            done,
+            stats: None,
+        })
+    }
+
+    #[async_trait]
+    impl LlmProvider for StubProvider {
+        async fn health_check(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn chat(&self, _messages: &[Message]) -> Result<ResponseStream> {
+            let items = std::mem::take(&mut *self.items.lock().unwrap());
+            Ok(Box::pin(futures_util::stream::iter(items)))
+        }
+
+        async fn chat_with_retries(
+            &self,
+            messages: &[Message],
+            _max_retries: u32,
+        ) -> Result<ResponseStream> {
+            self.chat(messages).await
+        }
+
+        async fn alternatives(&self, _messages: &[Message], n: u32) -> Result<Vec<String>> {
+            Ok((1..=n).map(|i| format!("Answer {}", i)).collect())
+        }
+
+        /// One layer reported at 0, 4, 9, 12, 55 and 100 percent.
+        async fn pull_model(&self) -> Result<PullStream> {
+            let layer = |completed| PullProgress {
+                status: "pulling layer".to_string(),
+                total: Some(100),
+                completed: Some(completed),
+            };
+            let mut reports = vec![PullProgress {
+                status: "pulling manifest".to_string(),
+                ..PullProgress::default()
+            }];
+            reports.extend([0, 4, 9, 12, 55, 100].map(layer));
+            reports.push(PullProgress {
+                status: "success".to_string(),
+                ..PullProgress::default()
+            });
+            Ok(Box::pin(futures_util::stream::iter(
+                reports.into_iter().map(Ok),
+            )))
+        }
+
+        fn name(&self) -> &str {
+            "stub"
+        }
+
+        fn model_name(&self) -> &str {
+            "stub-model"
+        }
+    }
+
+    /// Switches models freely; "tiny" has a 10-token context window, the rest 1000.
+    struct WindowProvider {
+        model: String,
+    }
+
+    #[async_trait]
+    impl LlmProvider for WindowProvider {
+        async fn health_check(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn chat(&self, _messages: &[Message]) -> Result<ResponseStream> {
+            Ok(Box::pin(futures_util::stream::empty()))
+        }
+
+        async fn chat_with_retries(
+            &self,
+            messages: &[Message],
+            _max_retries: u32,
+        ) -> Result<ResponseStream> {
+            self.chat(messages).await
+        }
+
+        async fn context_window(&self) -> Result<Option<u64>> {
+            Ok(Some(if self.model == "tiny" { 10 } else { 1000 }))
+        }
+
+        fn set_model(&mut self, model: &str) -> Result<()> {
+            self.model = model.to_string();
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "window"
+        }
+
+        fn model_name(&self) -> &str {
+            &self.model
+        }
+    }
+
+    /// Switches to "tiny" with 12 one-token messages under `policy`.
+    async fn switch_to_tiny(policy: ContextOverflowPolicy) -> (Agent, Session, String) {
+        let mut agent = Agent::with_provider(Box::new(WindowProvider {
+            model: "large".to_string(),
+        }));
+        let mut session = Session::new();
+        for i in 0..6 {
+            session.push_user(format!("q{}", i));
+            session.push_assistant(format!("a{}", i));
+        }
+        let mut output = RecordingOutput::default();
+        let cfg = AppConfig {
+            context_overflow: policy,
+            ..AppConfig::default()
+        };
+
+        switch_model(
+            "tiny",
+            &mut NoInput,
+            &mut output,
+            &mut agent,
+            &mut session,
+            &cfg,
+        )
+        .await
+        .unwrap();
+        (agent, session, output.written)
+    }
+
+    #[tokio::test]
+    async fn overflowing_switch_warns_with_estimate_and_limit() {
+        let (agent, session, written) = switch_to_tiny(ContextOverflowPolicy::Warn).await;
+
+        assert_eq!(agent.model_name(), "tiny");
+        assert_eq!(session.history.len(), 12);
+        assert!(written.contains("about 12 tokens, more than the 10 tokens tiny can take"));
+    }
+
+    #[tokio::test]
+    async fn overflowing_switch_compacts_the_oldest_messages() {
+        let (agent, session, written) = switch_to_tiny(ContextOverflowPolicy::Compact).await;
+
+        assert_eq!(agent.model_name(), "tiny");
+        assert_eq!(session.history.len(), 10);
+        assert_eq!(session.history[0].content, "q1");
+        assert!(written.contains("Dropped the 2 oldest messages"));
+    }
+
+    #[tokio::test]
+    async fn overflowing_switch_is_blocked() {
+        let (agent, session, written) = switch_to_tiny(ContextOverflowPolicy::Block).await;
+
+        assert_eq!(agent.model_name(), "large");
+        assert!(session.model_override.is_none());
+        assert_eq!(session.history.len(), 12);
+        assert!(written.contains("Staying on large"));
+        assert!(!written.contains("Switched"));
+    }
+
+    /// Answers each request with the next of its streams.
+    struct SequenceProvider {
+        answers: Mutex<VecDeque<ResponseStream>>,
+    }
+
+    impl SequenceProvider {
+        /// `first` for the first request, then a complete "Hello!".
+        fn new(first: ResponseStream) -> Self {
+            let full = futures_util::stream::iter(vec![chunk("Hello", false), chunk("!", true)]);
+            Self {
+                answers: Mutex::new(VecDeque::from([first, Box::pin(full) as ResponseStream])),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LlmProvider for SequenceProvider {
+        async fn health_check(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn chat(&self, _messages: &[Message]) -> Result<ResponseStream> {
+            let next = self.answers.lock().unwrap().pop_front();
+            next.ok_or_else(|| AppError::llm("no answers left"))
+        }
+
+        async fn chat_with_retries(
+            &self,
+            messages: &[Message],
+            _max_retries: u32,
+        ) -> Result<ResponseStream> {
+            self.chat(messages).await
+        }
+
+        fn name(&self) -> &str {
+            "sequence"
+        }
+
+        fn model_name(&self) -> &str {
+            "stub-model"
+        }
+    }
+
+    /// Keeps what was written, separating flushed text from text still buffered.
+    #[derive(Default)]
+    struct RecordingOutput {
+        buffered: String,
+        written: String,
+        finished: usize,
+    }
+
+    #[async_trait]
+    impl OutputSink for RecordingOutput {
+        async fn emit(&mut self, text: &str) -> Result<()> {
+            self.buffered.push_str(text);
+            self.buffered.push('\n');
+            self.flush().await
+        }
+
+        async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+            self.buffered.push_str(chunk);
+            Ok(())
+        }
+
+        async fn emit_error(&mut self, error: &str) -> Result<()> {
+            self.flush().await?;
+            self.written.push_str(&format!("Error: {}\n", error));
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<()> {
+            self.written.push_str(&std::mem::take(&mut self.buffered));
+            Ok(())
+        }
+
+        async fn finish_response(&mut self) -> Result<()> {
+            self.finished += 1;
+            self.flush().await?;
+            self.emit("").await
+        }
+    }
+
+    /// Yields the given lines, then end of input.
+    struct ScriptedInput(VecDeque<String>);
+
+    #[async_trait]
+    impl InputSource for ScriptedInput {
+        async fn next(&mut self) -> Result<Option<String>> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_closing_without_done_is_stream_ended() {
+        let agent = Agent::with_provider(Box::new(StubProvider::new(vec![
+            chunk("Partial ", false),
+            chunk("answer", false),
+        ])));
+        let mut session = Session::new();
+        let mut output = RecordingOutput::default();
+
+        let result = process_user_input(
+            &mut NoInput,
+            &mut output,
+            &agent,
+            &mut session,
+            &mut VecDeque::new(),
+            "Hello",
+            None,
+            &AppConfig::default(),
+            &CancellationToken::new(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(AppError::StreamEnded)));
+        assert!(output.written.contains("Partial answer"));
+        assert_eq!(output.finished, 1);
+        // The unanswered turn is not kept for the next request.
+        assert!(session.history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn picked_alternative_joins_the_history() {
+        let mut agent = Agent::with_provider(Box::new(StubProvider::new(Vec::new())));
+        let mut session = Session::new();
+        let mut output = RecordingOutput::default();
+        let cfg = AppConfig::default();
+
+        offer_alternatives(
+            &mut output,
+            &agent,
+            &mut session,
+            "Name a color",
+            3,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        assert!(output.written.contains("Alternative 3:\nAnswer 3"));
+        assert!(session.history.is_empty());
+
+        let outcome = handle_command(
+            Command::Pick(Some("2".to_string())),
+            &mut NoInput,
+            &mut output,
+            &mut agent,
+            &mut session,
+            &cfg,
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(outcome, CommandOutcome::Done));
+        assert!(session.alternatives.is_none());
+        let turns: Vec<&str> = session.history.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(turns, ["Name a color", "Answer 2"]);
+    }
+
+    #[tokio::test]
+    async fn switching_sessions_saves_the_one_left() {
+        let dir = std::env::temp_dir().join(format!("autoai-switch-{}", std::process::id()));
+        let mut agent = Agent::with_provider(Box::new(StubProvider::new(Vec::new())));
+        let mut output = RecordingOutput::default();
+        let cfg = AppConfig {
+            session_path: Some(dir.join("default.json")),
+            ..AppConfig::default()
+        };
+        let mut session = Session::new();
+        session.push_user("first question");
+
+        for name in ["work", "default"] {
+            handle_command(
+                Command::Switch(Some(name.to_string())),
+                &mut NoInput,
+                &mut output,
+                &mut agent,
+                &mut session,
+                &cfg,
+            )
+            .await
+            .unwrap();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(output
+            .written
+            .contains("Switched to session work (0 messages)."));
+        assert!(output
+            .written
+            .contains("Switched to session default (1 messages)."));
+        assert_eq!(session.name.as_deref(), Some("default"));
+    }
+
+    #[tokio::test]
+    async fn system_prompt_is_replaced_and_cleared() {
+        let mut agent = Agent::with_provider(Box::new(StubProvider::new(Vec::new())));
+        let mut session = Session::new();
+        let mut output = RecordingOutput::default();
+        let cfg = AppConfig::default();
+
+        for arg in ["Answer like a pirate.", "clear"] {
+            handle_command(
+                Command::System(Some(arg.to_string())),
+                &mut NoInput,
+                &mut output,
+                &mut agent,
+                &mut session,
+                &cfg,
+            )
+            .await
+            .unwrap();
+            if arg != "clear" {
+                assert_eq!(agent.system_prompt(), Some(arg));
+            }
+        }
+
+        assert!(agent.system_prompt().is_none());
+        assert!(output
+            .written
+            .contains("System prompt is now: Answer like a pirate."));
+        assert!(output.written.contains("System prompt cleared."));
+    }
+
+    #[tokio::test]
+    async fn completed_stream_is_kept_in_history() {
+        let agent = Agent::with_provider(Box::new(StubProvider::new(vec![
+            chunk("Hi", false),
+            chunk("!", true),
+        ])));
+        let mut session = Session::new();
+        let mut output = RecordingOutput::default();
+
+        let result = process_user_input(
+            &mut NoInput,
+            &mut output,
+            &agent,
+            &mut session,
+            &mut VecDeque::new(),
+            "Hello",
+            None,
+            &AppConfig::default(),
+            &CancellationToken::new(),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(session.history.len(), 2);
+        assert_eq!(session.history[1].content, "Hi!");
+    }
+
+    #[tokio::test]
+    async fn replay_compares_responses_with_whitespace_collapsed() {
+        let turn = |response: &str| RecordedTurn {
+            prompt: "Hello".to_string(),
+            response: response.to_string(),
+        };
+        let replay = |recorded| async move {
+            let agent = Agent::with_provider(Box::new(StubProvider::new(vec![chunk(
+                "Hi  there!\n",
+                true,
+            )])));
+            let mut output = RecordingOutput::default();
+            let matched = run_replay(
+                vec![turn(recorded)],
+                &mut output,
+                agent,
+                &AppConfig::default(),
+            )
+            .await
+            .unwrap();
+            (matched, output.written)
+        };
+
+        let (matched, written) = replay("Hi there!").await;
+        assert!(matched);
+        assert!(written.contains("1 unchanged, 0 changed, 0 failed"));
+
+        let (matched, written) = replay("Hello there!").await;
+        assert!(!matched);
+        assert!(written.contains("0 unchanged, 1 changed, 0 failed"));
+    }
+
+    #[test]
+    fn config_shows_changes_made_during_the_session() {
+        let mut agent = Agent::with_provider(Box::new(StubProvider::new(Vec::new())));
+        agent.set_response_prefix(Some("Sure,".to_string()));
+        let mut session = Session::new();
+        session.show_reasoning = Some(true);
+
+        let settings = effective_config(&agent, &session, &AppConfig::default());
+
+        let value = |name| {
+            settings
+                .iter()
+                .find(|(setting, _)| *setting == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(value("model"), Some("stub-model"));
+        assert_eq!(value("response prefix"), Some("Sure,"));
+        assert_eq!(value("show reasoning"), Some("on"));
+        assert_eq!(value("voice"), Some("off"));
+    }
+
+    #[tokio::test]
+    async fn pull_progress_without_a_terminal_logs_milestones() {
+        let agent = Agent::with_provider(Box::new(StubProvider::new(Vec::new())));
+        let mut output = RecordingOutput::default();
+
+        pull_model(&agent, &mut output).await.unwrap();
+
+        let lines: Vec<&str> = output.written.lines().skip(1).collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "pulling manifest");
+        assert!(lines[1].ends_with("  0%"));
+        assert!(lines[2].ends_with(" 12%"));
+        assert!(lines[3].ends_with(" 55%"));
+        assert!(lines[4].ends_with("100%"));
+        assert_eq!(lines[5], "✅ Model downloaded.");
+    }
+
+    fn dropped_connection() -> ResponseStream {
+        Box::pin(futures_util::stream::iter(vec![
+            chunk("Hel", false),
+            Err(AppError::service_unvailable("connection reset")),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn prompt_is_resent_after_reconnecting_when_auto() {
+        let mut agent = Agent::with_provider(Box::new(SequenceProvider::new(dropped_connection())));
+        let mut input = ScriptedInput(VecDeque::from(["Hi".to_string()]));
+        let mut output = RecordingOutput::default();
+        let mut session = Session::new();
+        let cfg = AppConfig {
+            resend_after_reconnect: Some(ResendPolicy::Auto),
+            ..AppConfig::default()
+        };
+
+        run_main_loop(&mut input, &mut output, &mut agent, &mut session, &cfg)
+            .await
+            .unwrap();
+
+        assert!(output.written.contains("sending the prompt again"));
+        assert_eq!(session.history.len(), 2);
+        assert_eq!(session.history[1].content, "Hello!");
+    }
+
+    #[tokio::test]
+    async fn piped_prompts_are_not_resent_by_default() {
+        let mut agent = Agent::with_provider(Box::new(SequenceProvider::new(dropped_connection())));
+        let mut input = ScriptedInput(VecDeque::from(["Hi".to_string()]));
+        let mut output = RecordingOutput::default();
+        let mut session = Session::new();
+
+        run_main_loop(
+            &mut input,
+            &mut output,
+            &mut agent,
+            &mut session,
+            &AppConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(output.written.contains("the prompt was not sent again"));
+        assert!(session.history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stalled_response_is_restarted() {
+        let started = futures_util::stream::iter(vec![chunk("Hel", false)]);
+        let stalled = started.chain(futures_util::stream::pending());
+        let agent = Agent::with_provider(Box::new(SequenceProvider::new(Box::pin(stalled))));
+        let mut session = Session::new();
+        let mut output = RecordingOutput::default();
+        let cfg = AppConfig {
+            restart_on_stall: true,
+            stall_timeout_secs: 1,
+            ..AppConfig::default()
+        };
+
+        let result = process_user_input(
+            &mut NoInput,
+            &mut output,
+            &agent,
+            &mut session,
+            &mut VecDeque::new(),
+            "Hello",
+            None,
+            &cfg,
+            &CancellationToken::new(),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert!(output
+            .written
+            .contains("[stalled, restarting the response]"));
+        assert_eq!(session.history.len(), 2);
+        assert_eq!(session.history[1].content, "Hello!");
+    }
+
+    #[tokio::test]
+    async fn one_shot_error_exit_keeps_the_partial_answer() {
+        let agent = Agent::with_provider(Box::new(StubProvider::new(vec![
+            chunk("Partial", false),
+            Err(AppError::Timeout { seconds: 5 }),
+        ])));
+        let mut output = RecordingOutput::default();
+
+        let result = run_once(&mut output, agent, "Hello", &AppConfig::default()).await;
+
+        assert!(matches!(result, Err(AppError::Timeout { seconds: 5 })));
+        assert!(output.written.contains("Partial"));
+        assert!(output.buffered.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fatal_error_exit_flushes_output() {
+        let agent = Agent::with_provider(Box::new(StubProvider::new(vec![
+            chunk("Partial", false),
+            Err(AppError::Config("broken".to_string())),
+        ])));
+        let input = ScriptedInput(VecDeque::from(["Hello".to_string()]));
+        let mut output = RecordingOutput::default();
+
+        run(input, &mut output, agent, &AppConfig::default())
+            .await
+            .unwrap();
+
+        assert!(output.written.contains("Partial"));
+        assert!(output.written.contains("broken"));
+        assert!(output.buffered.is_empty());
+    }
+
+    #[tokio::test]
+    async fn oversized_response_keeps_the_prefix_that_fits() {
+        let agent = Agent::with_provider(Box::new(StubProvider::new(vec![
+            chunk("abc", false),
+            chunk("déf", false),
+            chunk("never shown", true),
+        ])));
+        let mut session = Session::new();
+        let mut output = RecordingOutput::default();
+        let cfg = AppConfig {
+            // Byte 5 falls inside "é".
+            max_response_bytes: 5,
+            ..AppConfig::default()
+        };
+
+        process_user_input(
+            &mut NoInput,
+            &mut output,
+            &agent,
+            &mut session,
+            &mut VecDeque::new(),
+            "Hello",
+            None,
+            &cfg,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        let expected = format!("abcd{}", cfg.truncation.marker);
+        assert!(output.written.contains(&expected));
+        assert!(!output.written.contains("never shown"));
+    }
+}
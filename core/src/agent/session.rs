@@ -0,0 +1,164 @@
+use crate::llm::{GenerationStats, Message, MessageMeta, Role};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenStats {
+    #[serde(default)]
+    pub requests: u64,
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    /// Latency of the most recent request up to its first non-empty chunk.
+    #[serde(default)]
+    pub time_to_first_token_ms: Option<u64>,
+    /// Rough token estimate of the most recent prompt, taken before sending.
+    #[serde(default)]
+    pub prompt_tokens_estimate: Option<u64>,
+    /// Older messages dropped from the history to fit the most recent prompt.
+    #[serde(default)]
+    pub trimmed_messages: u64,
+}
+
+/// Answers offered for a prompt, waiting for /pick.
+#[derive(Debug, Clone)]
+pub struct Alternatives {
+    pub prompt: String,
+    pub answers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub history: Vec<Message>,
+    /// Scratchpad key-values set with `/set`, kept with the conversation.
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    #[serde(default)]
+    pub model_override: Option<String>,
+    #[serde(default)]
+    pub stats: TokenStats,
+    /// Set by /think; overrides `show_reasoning` from the config.
+    #[serde(default)]
+    pub show_reasoning: Option<bool>,
+    /// Set by /translate; overrides `voice.translate` from the config.
+    #[serde(default)]
+    pub translate: Option<bool>,
+    /// The named session this is, saved as `<name>.json` instead of the default file.
+    #[serde(skip)]
+    pub name: Option<String>,
+    /// Not saved; dropped when the next prompt is sent.
+    #[serde(skip)]
+    pub alternatives: Option<Alternatives>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_user(&mut self, content: impl Into<String>) {
+        self.push(Role::User, content.into());
+    }
+
+    /// Stores a reply without trailing whitespace, which would otherwise be
+    /// re-sent as context on every later turn.
+    pub fn push_assistant(&mut self, content: impl Into<String>) {
+        let mut content = content.into();
+        content.truncate(content.trim_end().len());
+        self.push(Role::Assistant, content);
+    }
+
+    /// Notes which model answered the latest prompt, and the token counts it
+    /// reported, on the prompt and its reply.
+    pub fn record_reply(&mut self, model: &str, stats: Option<&GenerationStats>) {
+        let Some(index) = self.history.iter().rposition(|m| m.role == Role::User) else {
+            return;
+        };
+        for message in &mut self.history[index..] {
+            let meta = message.meta.get_or_insert_with(MessageMeta::now);
+            meta.model = Some(model.to_string());
+            if message.role == Role::Assistant {
+                meta.prompt_tokens = stats.and_then(|s| s.prompt_tokens);
+                meta.completion_tokens = stats.and_then(|s| s.completion_tokens);
+            }
+        }
+    }
+
+    fn push(&mut self, role: Role, content: String) {
+        let mut message = Message::new(role, content);
+        message.meta = Some(MessageMeta::now());
+        self.history.push(message);
+    }
+
+    /// Removes the most recent exchange, returning its user message so it can be resent.
+    pub fn take_last_turn(&mut self) -> Option<String> {
+        let index = self.history.iter().rposition(|m| m.role == Role::User)?;
+        let text = self.history[index].content.clone();
+        self.history.truncate(index);
+        Some(text)
+    }
+
+    /// Drops the oldest messages beyond `max`, returning how many were removed.
+    pub fn trim_history(&mut self, max: usize) -> usize {
+        let excess = self.history.len().saturating_sub(max);
+        self.history.drain(..excess);
+        excess
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assistant_turns_are_stored_without_trailing_whitespace() {
+        let mut session = Session::new();
+        session.push_user("Hi");
+        session.push_assistant("  Hello there!\n\n \t");
+        session.push_assistant("\n");
+
+        assert_eq!(session.history[1].content, "  Hello there!");
+        assert_eq!(session.history[2].content, "");
+    }
+
+    #[test]
+    fn message_metadata_survives_a_round_trip() {
+        let mut session = Session::new();
+        session.push_user("Hi");
+        session.push_assistant("Hello!");
+        let stats = GenerationStats {
+            prompt_tokens: Some(12),
+            completion_tokens: Some(3),
+            eval_duration: None,
+        };
+        session.record_reply("llama3", Some(&stats));
+
+        let json = serde_json::to_string(&session).unwrap();
+        let restored: Session = serde_json::from_str(&json).unwrap();
+
+        let reply = restored.history[1].meta.as_ref().unwrap();
+        assert_eq!(reply, session.history[1].meta.as_ref().unwrap());
+        assert!(reply.timestamp_ms > 0);
+        assert_eq!(reply.model.as_deref(), Some("llama3"));
+        assert_eq!(reply.prompt_tokens, Some(12));
+        assert_eq!(reply.completion_tokens, Some(3));
+        let prompt = restored.history[0].meta.as_ref().unwrap();
+        assert_eq!(prompt.model.as_deref(), Some("llama3"));
+        assert_eq!(prompt.completion_tokens, None);
+    }
+
+    #[test]
+    fn messages_without_metadata_still_load_and_send_bare() {
+        let json = r#"{"history":[{"role":"user","content":"Hi"}]}"#;
+
+        let session: Session = serde_json::from_str(json).unwrap();
+
+        assert!(session.history[0].meta.is_none());
+        let mut message = session.history[0].clone();
+        message.meta = Some(MessageMeta::now());
+        let sent = serde_json::to_value(message.without_meta()).unwrap();
+        assert_eq!(sent, serde_json::json!({"role": "user", "content": "Hi"}));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::agent::session::Session;
+use crate::error::{AppError, Result};
+use std::path::{Path, PathBuf};
+
+/// Saves sessions as JSON; named sessions live next to the default file.
+pub struct SessionStore {
+    path: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Loads the default session, starting empty if it is missing or corrupt.
+    pub fn load_or_default(&self) -> Session {
+        if !self.path.exists() {
+            tracing::debug!("No saved session at {}", self.path.display());
+            return Session::new();
+        }
+
+        match Self::read(&self.path) {
+            Ok(session) => {
+                tracing::info!(
+                    "Restored {} messages from {}",
+                    session.history.len(),
+                    self.path.display()
+                );
+                session
+            }
+            Err(e) => {
+                tracing::warn!("Ignoring saved session {}: {}", self.path.display(), e);
+                Session::new()
+            }
+        }
+    }
+
+    pub fn save(&self, session: &Session) -> Result<()> {
+        Self::write(&self.path, session)
+    }
+
+    /// Saves `session` where it belongs: its named file, else the default one.
+    pub fn save_current(&self, session: &Session) -> Result<PathBuf> {
+        match session.name {
+            Some(ref name) => self.save_named(name, session),
+            None => self.save(session).map(|()| self.path.clone()),
+        }
+    }
+
+    pub fn save_named(&self, name: &str, session: &Session) -> Result<PathBuf> {
+        let path = self.named_path(name)?;
+        Self::write(&path, session)?;
+        Ok(path)
+    }
+
+    pub fn load_named(&self, name: &str) -> Result<Session> {
+        let path = self.named_path(name)?;
+        if !path.exists() {
+            return Err(AppError::invalid_input(format!(
+                "no saved session named {}",
+                name
+            )));
+        }
+        Self::read(&path)
+    }
+
+    /// Loads a named session, starting an empty one under that name if there is none.
+    pub fn open_named(&self, name: &str) -> Result<Session> {
+        let mut session = if self.named_path(name)?.exists() {
+            self.load_named(name)?
+        } else {
+            tracing::info!("Starting new session {}", name);
+            Session::new()
+        };
+        session.name = Some(name.to_string());
+        Ok(session)
+    }
+
+    /// Names of the saved sessions, the default one included, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names: Vec<String> = std::fs::read_dir(dir)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                    return None;
+                }
+                let name = path.file_stem()?.to_str()?.to_string();
+                self.named_path(&name).is_ok().then_some(name)
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn named_path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(AppError::invalid_input(
+                "session names may only contain letters, digits, '-' and '_'",
+            ));
+        }
+
+        Ok(self.path.with_file_name(format!("{}.json", name)))
+    }
+
+    fn read(path: &Path) -> Result<Session> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn write(path: &Path, session: &Session) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Write then rename so a crash never leaves a half-written session behind.
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(session)?)?;
+        std::fs::rename(&tmp, path)?;
+
+        tracing::debug!("Saved session to {}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vars_survive_a_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("autoai-store-{}", std::process::id()));
+        let store = SessionStore::new(dir.join("session.json"));
+
+        let mut session = Session::new();
+        session.push_user("hello");
+        session
+            .vars
+            .insert("project".to_string(), "autoAI".to_string());
+        session.model_override = Some("llama3".to_string());
+        store.save(&session).unwrap();
+        store.save_named("scratch", &session).unwrap();
+
+        for restored in [
+            store.load_or_default(),
+            store.load_named("scratch").unwrap(),
+        ] {
+            assert_eq!(
+                restored.vars.get("project").map(String::as_str),
+                Some("autoAI")
+            );
+            assert_eq!(restored.model_override.as_deref(), Some("llama3"));
+            assert_eq!(restored.history.len(), 1);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn named_sessions_are_listed_and_kept_apart() {
+        let dir = std::env::temp_dir().join(format!("autoai-named-{}", std::process::id()));
+        let store = SessionStore::new(dir.join("default.json"));
+
+        let mut work = store.open_named("work").unwrap();
+        assert!(work.history.is_empty());
+        work.push_user("deadline?");
+        work.vars.insert("team".to_string(), "core".to_string());
+        store.save_named("work", &work).unwrap();
+        store.save(&Session::new()).unwrap();
+
+        let names = store.list();
+        let reopened = store.open_named("work");
+        let home = store.open_named("home");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names.unwrap(), ["default", "work"]);
+        let reopened = reopened.unwrap();
+        assert_eq!(reopened.name.as_deref(), Some("work"));
+        assert_eq!(reopened.history.len(), 1);
+        let home = home.unwrap();
+        assert!(home.history.is_empty() && home.vars.is_empty());
+    }
+}
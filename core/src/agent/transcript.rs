@@ -0,0 +1,140 @@
+use crate::error::{AppError, Result};
+use crate::llm::GenerationStats;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One completed turn as written to the transcript log.
+#[derive(Debug, Serialize)]
+pub struct TranscriptRecord<'a> {
+    pub timestamp_ms: u64,
+    pub model: &'a str,
+    pub prompt: &'a str,
+    pub response: &'a str,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub tokens_per_sec: Option<f64>,
+}
+
+impl<'a> TranscriptRecord<'a> {
+    pub fn new(
+        model: &'a str,
+        prompt: &'a str,
+        response: &'a str,
+        stats: Option<&GenerationStats>,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self {
+            timestamp_ms,
+            model,
+            prompt,
+            response,
+            prompt_tokens: stats.and_then(|s| s.prompt_tokens),
+            completion_tokens: stats.and_then(|s| s.completion_tokens),
+            tokens_per_sec: stats.and_then(GenerationStats::tokens_per_sec),
+        }
+    }
+}
+
+/// Appends one JSON line per turn, separate from the tracing logs.
+pub struct TranscriptLog {
+    path: PathBuf,
+}
+
+impl TranscriptLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn append(&self, record: &TranscriptRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        // One write per record in append mode keeps lines from concurrent runs intact.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// A turn read back from a transcript log for --replay.
+#[derive(Debug, Deserialize)]
+pub struct RecordedTurn {
+    pub prompt: String,
+    pub response: String,
+}
+
+/// Reads every turn of the transcript log at `path`, in order.
+pub fn read_turns(path: &Path) -> Result<Vec<RecordedTurn>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                AppError::invalid_input(format!("{} line {}: {}", path.display(), index + 1, e))
+            })
+        })
+        .collect()
+}
+
+/// FNV-1a hash of `text` with runs of whitespace collapsed and the ends
+/// trimmed, so reflowed answers with the same words hash the same.
+pub fn response_hash(text: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = OFFSET_BASIS;
+    for (i, word) in text.split_whitespace().enumerate() {
+        if i > 0 {
+            hash = (hash ^ u64::from(b' ')).wrapping_mul(PRIME);
+        }
+        for byte in word.bytes() {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_hash_ignores_whitespace_layout_only() {
+        let baseline = response_hash("The answer is 4.");
+
+        assert_eq!(response_hash("  The answer\n\tis   4.\n"), baseline);
+        assert_ne!(response_hash("The answer is 5."), baseline);
+        assert_ne!(response_hash("Theansweris4."), baseline);
+    }
+
+    #[test]
+    fn recorded_turns_are_read_in_order() {
+        let path = std::env::temp_dir().join(format!("autoai-replay-{}.jsonl", std::process::id()));
+        let log = TranscriptLog::new(&path);
+        for (prompt, response) in [("One?", "1"), ("Two?", "2")] {
+            log.append(&TranscriptRecord::new("m", prompt, response, None))
+                .unwrap();
+        }
+
+        let turns = read_turns(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let turns = turns.unwrap();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[1].prompt, "Two?");
+        assert_eq!(turns[1].response, "2");
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::{AppConfig, OpenAiConfig, ProviderKind, VoiceConfig};
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "autoai",
+    version,
+    about = "Chat with a local LLM by text or voice"
+)]
+pub struct Cli {
+    /// Config file to load (.toml, .yaml or .json) instead of searching config/
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<std::path::PathBuf>,
+
+    /// Answer this prompt, print the response and exit
+    #[arg(value_name = "PROMPT", conflicts_with_all = ["batch_file", "stdin"])]
+    pub prompt: Vec<String>,
+
+    /// Read a one-shot prompt from standard input instead of the arguments
+    #[arg(long, conflicts_with = "batch_file")]
+    pub stdin: bool,
+
+    /// List available audio hosts and the configured host's input devices, then exit
+    #[arg(long)]
+    pub list_audio_devices: bool,
+
+    /// Transcribe a WAV file with the configured voice model, print the text and exit
+    #[arg(long, value_name = "PATH")]
+    pub transcribe_file: Option<std::path::PathBuf>,
+
+    /// Run each prompt in this file as an independent turn, then exit
+    #[arg(long, value_name = "PATH")]
+    pub batch_file: Option<String>,
+
+    /// Line separating prompts in the batch file
+    #[arg(
+        long,
+        value_name = "LINE",
+        default_value = "---",
+        requires = "batch_file"
+    )]
+    pub batch_delimiter: String,
+
+    /// Send the prompts of this transcript log again and exit non-zero if any
+    /// response changed (compared with whitespace collapsed)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["prompt", "stdin", "batch_file"])]
+    pub replay: Option<std::path::PathBuf>,
+
+    /// Resume the named session, or start it, instead of the default one
+    #[arg(long, value_name = "NAME")]
+    pub session: Option<String>,
+
+    /// Chat model to use instead of the configured one
+    #[arg(long, value_name = "NAME")]
+    pub model: Option<String>,
+
+    /// Server address of the active provider, e.g. http://gpu-box:11434
+    #[arg(long, value_name = "URL")]
+    pub base_url: Option<String>,
+
+    /// Sampling temperature
+    #[arg(long, value_name = "VALUE")]
+    pub temperature: Option<f32>,
+
+    /// Whisper model file; enables voice input if the config has no [voice] section
+    #[arg(long, value_name = "PATH", conflicts_with = "no_voice")]
+    pub voice_model: Option<String>,
+
+    /// Use text input even when the config has a [voice] section
+    #[arg(long)]
+    pub no_voice: bool,
+
+    /// Start errors with their code, e.g. [timeout]
+    #[arg(long)]
+    pub show_error_codes: bool,
+}
+
+impl Cli {
+    /// Applies the flags that were given on top of the file and environment settings.
+    pub fn apply_overrides(&self, cfg: &mut AppConfig) {
+        if let Some(ref model) = self.model {
+            match active_openai(cfg) {
+                Some(openai) => openai.model_name = model.clone(),
+                None => {
+                    cfg.ollama.model_name = model.clone();
+                    // An explicit model wins over the preference list.
+                    cfg.ollama.model_preferences.clear();
+                }
+            }
+        }
+        if let Some(ref base_url) = self.base_url {
+            match active_openai(cfg) {
+                Some(openai) => openai.base_url = base_url.clone(),
+                None => cfg.ollama.base_url = base_url.clone(),
+            }
+        }
+        if let Some(temperature) = self.temperature {
+            match active_openai(cfg) {
+                Some(openai) => openai.temperature = Some(temperature),
+                None => cfg.ollama.sampling.temperature = Some(temperature),
+            }
+        }
+        if let Some(ref model_path) = self.voice_model {
+            cfg.voice
+                .get_or_insert_with(VoiceConfig::default)
+                .model_path = model_path.clone();
+        }
+        if self.no_voice {
+            cfg.voice = None;
+        }
+        if self.show_error_codes {
+            cfg.show_error_codes = true;
+        }
+        if let Some(ref session) = self.session {
+            cfg.session = Some(session.clone());
+        }
+    }
+}
+
+/// The [openai] section when it is the active provider, so flags apply there instead of [ollama].
+fn active_openai(cfg: &mut AppConfig) -> Option<&mut OpenAiConfig> {
+    match cfg.provider {
+        ProviderKind::OpenAi => cfg.openai.as_mut(),
+        ProviderKind::Ollama => None,
+    }
+}
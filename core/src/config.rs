@@ -1,15 +1,103 @@
 use crate::error::{AppError, Result};
-use serde::Deserialize;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Config files `load` looks for, in order of preference.
+/// Upper bound for `completions`; each one is a full request.
+pub const MAX_COMPLETIONS: u32 = 5;
+
+const CONFIG_CANDIDATES: &[&str] = &[
+    "config/config.toml",
+    "config/config.yaml",
+    "config/config.json",
+];
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct OllamaConfig {
+    /// When unset, taken from `OLLAMA_HOST`, else the local default.
+    #[serde(default = "default_base_url")]
     pub base_url: String,
     pub model_name: String,
     #[serde(default = "default_timeout")]
     pub timeout_secs: u64,
+    #[serde(default = "default_stream_idle_timeout")]
+    pub stream_idle_timeout_secs: u64,
     #[serde(default = "default_max_retries")]
     pub max_retries: u32,
+    #[serde(default)]
+    pub api_mode: ApiMode,
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    #[serde(default)]
+    pub max_history_messages: Option<usize>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub retry_budget: Option<u32>,
+    #[serde(default = "default_retry_budget_window")]
+    pub retry_budget_window_secs: u64,
+    #[serde(default)]
+    pub tls_ca_cert: Option<String>,
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+    #[serde(default)]
+    pub sampling: SamplingConfig,
+    #[serde(default)]
+    pub extra_options: HashMap<String, serde_json::Value>,
+    /// Generation ends before any of these strings.
+    #[serde(default)]
+    pub stop: Vec<String>,
+    #[serde(default)]
+    pub model_preferences: Vec<String>,
+    #[serde(default)]
+    pub auto_pull: bool,
+    /// Model used for embeddings; defaults to model_name.
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default = "default_health_path")]
+    pub health_path: String,
+    #[serde(default)]
+    pub health_method: HealthMethod,
+    #[serde(default = "default_health_cache_ttl")]
+    pub health_cache_ttl_secs: u64,
+    #[serde(default)]
+    pub log_request_bodies: bool,
+}
+
+/// Sampling options sent under the request's `options` key; unset fields use Ollama's defaults.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct SamplingConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiMode {
+    /// `/api/generate` with the conversation flattened into one prompt.
+    Generate,
+    /// `/api/chat` with role-tagged messages.
+    #[default]
+    Chat,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthMethod {
+    #[default]
+    Get,
+    Head,
+}
+
+fn default_base_url() -> String {
+    "http://localhost:11434".to_string()
 }
 
 fn default_timeout() -> u64 {
@@ -18,18 +106,88 @@ fn default_timeout() -> u64 {
 fn default_max_retries() -> u32 {
     3
 }
+fn default_stream_idle_timeout() -> u64 {
+    60
+}
+fn default_retry_budget_window() -> u64 {
+    600
+}
+fn default_stream() -> bool {
+    true
+}
+fn default_health_path() -> String {
+    "/api/tags".to_string()
+}
+fn default_health_cache_ttl() -> u64 {
+    5
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct VoiceConfig {
     pub model_path: String,
     #[serde(default = "default_language")]
     pub language: String,
-    #[serde(default = "default_silience_threshold")]
+    #[serde(
+        default = "default_silience_threshold",
+        alias = "silence_threshold_secs"
+    )]
     pub silience_threshold_secs: f32,
     #[serde(default = "default_max_duration")]
     pub max_duration_secs: f32,
     #[serde(default)]
     pub translate: bool,
+    #[serde(default)]
+    pub audio_buffer_frames: Option<u32>,
+    #[serde(default = "default_min_recording")]
+    pub min_recording_secs: f32,
+    #[serde(default = "default_max_empty_retries")]
+    pub max_empty_retries: u32,
+    #[serde(default = "default_prompt_on_empty")]
+    pub prompt_on_empty: bool,
+    #[serde(default)]
+    pub audio_host: Option<String>,
+    #[serde(default)]
+    pub device_name: Option<String>,
+    #[serde(default)]
+    pub audio_cues: bool,
+    #[serde(default)]
+    pub normalize_transcript: bool,
+    #[serde(default)]
+    pub speak_responses: bool,
+    #[serde(default = "default_tts_command")]
+    pub tts_command: String,
+    #[serde(default)]
+    pub mode: VoiceMode,
+    /// RMS energy above which a chunk counts as voice.
+    #[serde(default = "default_energy_threshold")]
+    pub energy_threshold: f32,
+    /// Derive the threshold from the measured background noise instead.
+    #[serde(default)]
+    pub adaptive_threshold: bool,
+    #[serde(default = "default_noise_floor_multiplier")]
+    pub noise_floor_multiplier: f32,
+    /// Each recording is also written here as a 16 kHz mono WAV before transcription.
+    #[serde(default)]
+    pub save_recordings_dir: Option<PathBuf>,
+    /// Transcribe long recordings every few seconds while they are still running.
+    #[serde(default)]
+    pub interim_results: bool,
+    /// Transcribe with per-word timings, printed by --transcribe-file.
+    #[serde(default)]
+    pub timestamps: bool,
+    /// Spoken phrases run as the slash command they map to instead of being sent.
+    #[serde(default = "default_voice_commands")]
+    pub commands: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VoiceMode {
+    /// Starts recording on voice energy and stops after silence.
+    #[default]
+    Vad,
+    /// Records only while the spacebar is held.
+    PushToTalk,
 }
 
 fn default_language() -> String {
@@ -44,6 +202,45 @@ fn default_max_duration() -> f32 {
     30.0
 }
 
+fn default_min_recording() -> f32 {
+    0.3
+}
+
+fn default_max_empty_retries() -> u32 {
+    3
+}
+
+fn default_energy_threshold() -> f32 {
+    0.01
+}
+
+fn default_noise_floor_multiplier() -> f32 {
+    3.0
+}
+
+fn default_tts_command() -> String {
+    "espeak-ng --stdin".to_string()
+}
+
+fn default_prompt_on_empty() -> bool {
+    true
+}
+
+fn default_voice_commands() -> HashMap<String, String> {
+    [
+        ("reset", "/reset"),
+        ("retry", "/retry"),
+        ("help", "/help"),
+        ("translate on", "/translate on"),
+        ("translate off", "/translate off"),
+        ("exit", "/quit"),
+        ("quit", "/quit"),
+    ]
+    .into_iter()
+    .map(|(phrase, command)| (phrase.to_string(), command.to_string()))
+    .collect()
+}
+
 impl Default for VoiceConfig {
     fn default() -> Self {
         Self {
@@ -52,29 +249,456 @@ impl Default for VoiceConfig {
             silience_threshold_secs: default_silience_threshold(),
             max_duration_secs: default_max_duration(),
             translate: false,
+            audio_buffer_frames: None,
+            min_recording_secs: default_min_recording(),
+            max_empty_retries: default_max_empty_retries(),
+            prompt_on_empty: default_prompt_on_empty(),
+            audio_host: None,
+            device_name: None,
+            audio_cues: false,
+            normalize_transcript: false,
+            speak_responses: false,
+            tts_command: default_tts_command(),
+            mode: VoiceMode::default(),
+            energy_threshold: default_energy_threshold(),
+            adaptive_threshold: false,
+            noise_floor_multiplier: default_noise_floor_multiplier(),
+            save_recordings_dir: None,
+            interim_results: false,
+            timestamps: false,
+            commands: default_voice_commands(),
         }
     }
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct OpenAiConfig {
+    #[serde(default = "default_openai_base_url")]
+    pub base_url: String,
+    /// Falls back to the OPENAI_API_KEY environment variable; optional for local servers.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    pub model_name: String,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_stream_idle_timeout")]
+    pub stream_idle_timeout_secs: u64,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+}
+
+fn default_openai_base_url() -> String {
+    "https://api.openai.com".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Ollama,
+    OpenAi,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct AppConfig {
+    #[serde(default)]
+    pub provider: ProviderKind,
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    #[serde(default = "default_prompt")]
+    pub prompt: String,
+    #[serde(default)]
+    pub idle_exit_secs: Option<u64>,
+    #[serde(default)]
+    pub output_fifo: Option<String>,
+    #[serde(default)]
+    pub coalesce_window_ms: Option<u64>,
+    #[serde(default)]
+    pub input_lossy_utf8: bool,
+    /// Prompts span lines until one equal to this; empty ends at a blank line.
+    #[serde(default)]
+    pub input_terminator: Option<String>,
+    /// Line editor history file, kept between runs; an empty path keeps
+    /// history for this run only.
+    #[serde(default = "default_input_history_path")]
+    pub input_history_path: Option<PathBuf>,
+    #[serde(default)]
+    pub blank_lines: BlankLinePolicies,
+    #[serde(default = "default_abort_keyword")]
+    pub abort_keyword: String,
+    #[serde(default = "default_keep_cancelled_in_history")]
+    pub keep_cancelled_in_history: bool,
+    #[serde(default)]
+    pub truncation: Truncation,
+    #[serde(default)]
+    pub stream_granularity: StreamGranularity,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    #[serde(default)]
+    pub highlight_code: bool,
+    /// Prefix errors in plain and markdown output with their code, e.g. `[timeout]`.
+    #[serde(default)]
+    pub show_error_codes: bool,
+    #[serde(default)]
+    pub max_display_tokens_per_sec: Option<u32>,
+    #[serde(default)]
+    pub show_prompt_estimate: bool,
+    #[serde(default)]
+    pub show_stats: bool,
+    #[serde(default = "default_repeated_error_threshold")]
+    pub repeated_error_threshold: Option<u32>,
+    #[serde(default)]
+    pub exit_on_repeated_errors: bool,
+    /// Attempts for a response regenerated with /retry; unset uses the provider's max_retries.
+    #[serde(default)]
+    pub retry_command_max_retries: Option<u32>,
+    /// Re-send the prompt when a started response goes quiet for `stall_timeout_secs`.
+    #[serde(default)]
+    pub restart_on_stall: bool,
+    #[serde(default = "default_stall_timeout_secs")]
+    pub stall_timeout_secs: u64,
+    #[serde(default = "default_max_stall_restarts")]
+    pub max_stall_restarts: u32,
+    /// Show `<think>` reasoning (dimmed) instead of stripping it; /think toggles it.
+    #[serde(default)]
+    pub show_reasoning: bool,
+    /// Replies are seeded with this text and continue from it; /prefix changes it.
+    #[serde(default)]
+    pub response_prefix: Option<String>,
+    /// Answers generated per prompt; above 1 they are shown numbered for /pick.
+    #[serde(default = "default_completions")]
+    pub completions: u32,
+    #[serde(default)]
+    pub session_path: Option<PathBuf>,
+    /// Named session resumed (or started) instead of the default one; --session sets it.
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Every completed turn is appended here as one JSON line.
+    #[serde(default)]
+    pub transcript_log: Option<PathBuf>,
+    /// Chunks of /ingest-ed documents added to each prompt.
+    #[serde(default = "default_context_top_k")]
+    pub context_top_k: usize,
+    /// What happens to the conversation when /model switches models.
+    #[serde(default)]
+    pub model_switch: ModelSwitchPolicy,
+    /// What /model does when the kept history no longer fits the new model's context window.
+    #[serde(default)]
+    pub context_overflow: ContextOverflowPolicy,
+    /// Unset asks in interactive sessions and never resends otherwise.
+    #[serde(default)]
+    pub resend_after_reconnect: Option<ResendPolicy>,
     pub ollama: OllamaConfig,
+    #[serde(default)]
+    pub openai: Option<OpenAiConfig>,
+    /// Ollama endpoints tried in order when the primary provider is unreachable.
+    #[serde(default)]
+    pub fallback_providers: Vec<FallbackProvider>,
     pub voice: Option<VoiceConfig>,
 }
 
+/// A secondary Ollama endpoint; every setting but the address is taken from `[ollama]`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FallbackProvider {
+    pub base_url: String,
+    /// Defaults to the model the primary provider resolves, including one
+    /// picked from `ollama.model_preferences`.
+    #[serde(default)]
+    pub model_name: Option<String>,
+}
+
+impl FallbackProvider {
+    /// Without its own `model_name` the fallback keeps the primary's model and
+    /// preferences until the agent hands it the resolved model.
+    pub fn ollama_config(&self, primary: &OllamaConfig) -> OllamaConfig {
+        match self.model_name {
+            Some(ref model_name) => OllamaConfig {
+                base_url: self.base_url.clone(),
+                model_name: model_name.clone(),
+                model_preferences: Vec::new(),
+                ..primary.clone()
+            },
+            None => OllamaConfig {
+                base_url: self.base_url.clone(),
+                ..primary.clone()
+            },
+        }
+    }
+
+    pub fn follows_primary_model(&self) -> bool {
+        self.model_name.is_none()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Plain,
+    Markdown,
+    /// One JSON object per line: chunk, message, error and done events.
+    Jsonl,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelSwitchPolicy {
+    /// Keep the history; `context_overflow` decides what happens if it no longer fits.
+    #[default]
+    Keep,
+    Clear,
+    /// Ask whether to clear the history; keeps it when nobody can answer.
+    Prompt,
+}
+
+/// How /model handles a history estimated to be larger than the new model's context window.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextOverflowPolicy {
+    /// Drop the oldest messages until the history fits.
+    Compact,
+    #[default]
+    Warn,
+    /// Stay on the current model.
+    Block,
+}
+
+/// Whether a prompt cut off by a lost connection is sent again once the
+/// backend is reachable.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResendPolicy {
+    Auto,
+    /// Ask y/N; never resends when nobody can answer.
+    Ask,
+    Never,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamGranularity {
+    #[default]
+    Token,
+    Line,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlankLinePolicy {
+    #[default]
+    Skip,
+    ResubmitLast,
+    /// Submit the blank line, so the runtime shows the prompt again.
+    PassThrough,
+}
+
+/// Blank input handling per input mode; a single policy applies to all of them.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(from = "BlankLineSetting")]
+pub struct BlankLinePolicies {
+    /// The interactive line editor.
+    pub text: BlankLinePolicy,
+    /// Lines piped to stdin.
+    pub stdin: BlankLinePolicy,
+    /// Empty prompts between delimiters in a batch file.
+    pub batch: BlankLinePolicy,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BlankLineSetting {
+    All(BlankLinePolicy),
+    PerMode {
+        #[serde(default)]
+        text: BlankLinePolicy,
+        #[serde(default)]
+        stdin: BlankLinePolicy,
+        #[serde(default)]
+        batch: BlankLinePolicy,
+    },
+}
+
+impl From<BlankLineSetting> for BlankLinePolicies {
+    fn from(setting: BlankLineSetting) -> Self {
+        match setting {
+            BlankLineSetting::All(policy) => Self {
+                text: policy,
+                stdin: policy,
+                batch: policy,
+            },
+            BlankLineSetting::PerMode { text, stdin, batch } => Self { text, stdin, batch },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CutPosition {
+    Head,
+    #[default]
+    Tail,
+    Middle,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Truncation {
+    #[serde(default = "default_truncation_marker")]
+    pub marker: String,
+    #[serde(default)]
+    pub cut: CutPosition,
+}
+
+fn default_truncation_marker() -> String {
+    "…".to_string()
+}
+
+impl Default for Truncation {
+    fn default() -> Self {
+        Self {
+            marker: default_truncation_marker(),
+            cut: CutPosition::default(),
+        }
+    }
+}
+
+impl Truncation {
+    /// Keeps at most `max_bytes` of `text`, cutting at `self.cut` and marking the cut.
+    pub fn apply(&self, text: &str, max_bytes: usize) -> String {
+        if text.len() <= max_bytes {
+            return text.to_string();
+        }
+
+        match self.cut {
+            CutPosition::Tail => {
+                let end = floor_char_boundary(text, max_bytes);
+                format!("{}{}", &text[..end], self.marker)
+            }
+            CutPosition::Head => {
+                let start = ceil_char_boundary(text, text.len() - max_bytes);
+                format!("{}{}", self.marker, &text[start..])
+            }
+            CutPosition::Middle => {
+                let end = floor_char_boundary(text, max_bytes / 2);
+                let start = ceil_char_boundary(text, text.len() - (max_bytes - max_bytes / 2));
+                format!("{}{}{}", &text[..end], self.marker, &text[start..])
+            }
+        }
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Expands `OLLAMA_HOST` forms like `0.0.0.0`, `:11434` or `host:port` into a full URL,
+/// using the same defaults as the Ollama CLI.
+fn normalize_ollama_host(host: &str) -> String {
+    const DEFAULT_HOST: &str = "127.0.0.1";
+    const DEFAULT_PORT: u16 = 11434;
+
+    let host = host.trim().trim_end_matches('/');
+    let (scheme, rest) = match host.split_once("://") {
+        Some((scheme, rest)) => (scheme, rest),
+        None => ("http", host),
+    };
+
+    let has_port = match rest.rsplit_once(':') {
+        // A bare IPv6 address such as "[::1]" has colons but no port.
+        Some((_, port)) => !port.contains(']'),
+        None => false,
+    };
+
+    let rest = match rest.strip_prefix(':') {
+        Some(port) => format!("{}:{}", DEFAULT_HOST, port),
+        None if rest.is_empty() => DEFAULT_HOST.to_string(),
+        None => rest.to_string(),
+    };
+
+    if has_port {
+        format!("{}://{}", scheme, rest)
+    } else {
+        format!("{}://{}:{}", scheme, rest, DEFAULT_PORT)
+    }
+}
+
+fn default_keep_cancelled_in_history() -> bool {
+    true
+}
+
+fn default_input_history_path() -> Option<PathBuf> {
+    Some(PathBuf::from(".autoai_history"))
+}
+
+fn default_repeated_error_threshold() -> Option<u32> {
+    Some(3)
+}
+
+fn default_context_top_k() -> usize {
+    3
+}
+
+fn default_max_response_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_prompt() -> String {
+    "> ".to_string()
+}
+
+fn default_completions() -> u32 {
+    1
+}
+
+fn default_stall_timeout_secs() -> u64 {
+    15
+}
+
+fn default_max_stall_restarts() -> u32 {
+    2
+}
+
+fn default_abort_keyword() -> String {
+    "stop".to_string()
+}
+
 impl AppConfig {
     pub fn load() -> Result<Self> {
-        Self::load_from_path("config/config.toml")
+        let path = CONFIG_CANDIDATES
+            .iter()
+            .map(Path::new)
+            .find(|path| path.exists())
+            // None present: let the TOML path produce the "not found" error.
+            .unwrap_or_else(|| Path::new(CONFIG_CANDIDATES[0]));
+        Self::load_from_path(path)
     }
 
+    /// Reads the file and environment without validating; call `validate` once
+    /// command-line overrides are applied.
     pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
 
         tracing::info!("Loading configuration from: {}", path.display());
 
-        let cfg = config::Config::builder()
-            .add_source(config::File::from(path))
-            .add_source(config::Environment::with_prefix("APP").separator("__"))
+        let format = Self::file_format(path)?;
+        let mut builder = config::Config::builder()
+            .add_source(config::File::from(path).format(format))
+            .add_source(config::Environment::with_prefix("APP").separator("__"));
+        if let Some(base_url) = Self::ollama_host_fallback(&builder)? {
+            builder = builder.set_default("ollama.base_url", base_url)?;
+        }
+        let cfg = builder
             .build()
             .map_err(|e| AppError::Config(format!("Failed to build config: {}", e)))?;
 
@@ -82,27 +706,89 @@ impl AppConfig {
             .try_deserialize()
             .map_err(|e| AppError::Config(format!("Failed to deserialize: {}", e)))?;
 
-        cfg.validate()?;
-
         tracing::info!("Configuration loaded successfully");
         Ok(cfg)
     }
 
-    fn validate(&self) -> Result<()> {
+    fn file_format(path: &Path) -> Result<config::FileFormat> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("toml") => Ok(config::FileFormat::Toml),
+            Some("yaml" | "yml") => Ok(config::FileFormat::Yaml),
+            Some("json") => Ok(config::FileFormat::Json),
+            _ => Err(AppError::config(format!(
+                "Unsupported config file {}: expected a .toml, .yaml, .yml or .json extension",
+                path.display()
+            ))),
+        }
+    }
+
+    /// `OLLAMA_HOST`, as a base_url, when neither the file nor
+    /// `APP__OLLAMA__BASE_URL` sets one.
+    fn ollama_host_fallback(
+        builder: &config::ConfigBuilder<config::builder::DefaultState>,
+    ) -> Result<Option<String>> {
+        let explicit = builder
+            .build_cloned()
+            .map_err(|e| AppError::Config(format!("Failed to build config: {}", e)))?
+            .get_string("ollama.base_url")
+            .is_ok();
+        if explicit {
+            tracing::info!("Using ollama base_url from the config file or APP__OLLAMA__BASE_URL");
+            return Ok(None);
+        }
+
+        match std::env::var("OLLAMA_HOST") {
+            Ok(host) if !host.trim().is_empty() => {
+                let base_url = normalize_ollama_host(&host);
+                tracing::info!("Using ollama base_url from OLLAMA_HOST: {}", base_url);
+                Ok(Some(base_url))
+            }
+            _ => {
+                tracing::info!("Using the default ollama base_url");
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
         if self.ollama.base_url.is_empty() {
             return Err(AppError::Config("base_url cannot be empty".to_string()));
         }
 
-        if !self.ollama.base_url.starts_with("http://")
+        if let Some(socket_path) = self.ollama.base_url.strip_prefix("unix://") {
+            if !Path::new(socket_path).exists() {
+                return Err(AppError::Config(format!(
+                    "unix socket does not exist: {}",
+                    socket_path
+                )));
+            }
+        } else if !self.ollama.base_url.starts_with("http://")
             && !self.ollama.base_url.starts_with("https://")
         {
             return Err(AppError::Config(
-                "base_url must start with http:// or https://".to_string(),
+                "base_url must start with http://, https:// or unix://".to_string(),
+            ));
+        }
+
+        if self.ollama.model_name.is_empty() && self.ollama.model_preferences.is_empty() {
+            return Err(AppError::Config(
+                "model_name cannot be empty unless model_preferences is set".to_string(),
             ));
         }
 
-        if self.ollama.model_name.is_empty() {
-            return Err(AppError::Config("model_name cannot be empty".to_string()));
+        if self
+            .ollama
+            .model_preferences
+            .iter()
+            .any(|m| m.trim().is_empty())
+        {
+            return Err(AppError::Config(
+                "model_preferences cannot contain empty names".to_string(),
+            ));
         }
 
         if self.ollama.timeout_secs == 0 {
@@ -111,6 +797,185 @@ impl AppConfig {
             ));
         }
 
+        if !self.ollama.health_path.starts_with('/') {
+            return Err(AppError::Config(
+                "health_path must start with /".to_string(),
+            ));
+        }
+
+        if let Some(ref ca_cert) = self.ollama.tls_ca_cert {
+            if !Path::new(ca_cert).exists() {
+                return Err(AppError::Config(format!(
+                    "tls_ca_cert does not exist: {}",
+                    ca_cert
+                )));
+            }
+        }
+
+        if self
+            .ollama
+            .extra_options
+            .keys()
+            .any(|k| k.trim().is_empty())
+        {
+            return Err(AppError::Config(
+                "ollama.extra_options keys cannot be empty".to_string(),
+            ));
+        }
+
+        if self.restart_on_stall && self.stall_timeout_secs == 0 {
+            return Err(AppError::Config(
+                "stall_timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.session.is_some() && self.session_path.is_none() {
+            return Err(AppError::Config(
+                "a named session needs session_path, whose directory holds the sessions"
+                    .to_string(),
+            ));
+        }
+
+        if self.idle_exit_secs == Some(0) {
+            return Err(AppError::Config(
+                "idle_exit_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.coalesce_window_ms == Some(0) {
+            return Err(AppError::Config(
+                "coalesce_window_ms must be greater than 0".to_string(),
+            ));
+        }
+
+        let sampling = &self.ollama.sampling;
+        if sampling.temperature.is_some_and(|t| t < 0.0) {
+            return Err(AppError::Config(
+                "sampling.temperature must not be negative".to_string(),
+            ));
+        }
+        if sampling.top_p.is_some_and(|p| p <= 0.0 || p > 1.0) {
+            return Err(AppError::Config(
+                "sampling.top_p must be in (0, 1]".to_string(),
+            ));
+        }
+        if sampling.repeat_penalty.is_some_and(|p| p < 0.0) {
+            return Err(AppError::Config(
+                "sampling.repeat_penalty must not be negative".to_string(),
+            ));
+        }
+
+        if self.provider == ProviderKind::OpenAi {
+            match self.openai {
+                None => {
+                    return Err(AppError::Config(
+                        "provider = \"openai\" needs an [openai] section".to_string(),
+                    ))
+                }
+                Some(ref openai) if openai.model_name.is_empty() => {
+                    return Err(AppError::Config(
+                        "openai.model_name cannot be empty".to_string(),
+                    ))
+                }
+                Some(ref openai)
+                    if !openai.base_url.starts_with("http://")
+                        && !openai.base_url.starts_with("https://") =>
+                {
+                    return Err(AppError::Config(
+                        "openai.base_url must start with http:// or https://".to_string(),
+                    ))
+                }
+                Some(ref openai) if openai.temperature.is_some_and(|t| t < 0.0) => {
+                    return Err(AppError::Config(
+                        "openai.temperature must not be negative".to_string(),
+                    ))
+                }
+                Some(ref openai) if openai.stream_idle_timeout_secs == 0 => {
+                    return Err(AppError::Config(
+                        "openai.stream_idle_timeout_secs must be greater than 0".to_string(),
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for fallback in &self.fallback_providers {
+            if !fallback.base_url.starts_with("http://")
+                && !fallback.base_url.starts_with("https://")
+                && !fallback.base_url.starts_with("unix://")
+            {
+                return Err(AppError::Config(format!(
+                    "fallback_providers base_url must start with http://, https:// or unix://: {}",
+                    fallback.base_url
+                )));
+            }
+        }
+
+        if let Some(ref system_prompt) = self.ollama.system_prompt {
+            const MAX_SYSTEM_PROMPT_CHARS: usize = 8000;
+            if system_prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS {
+                return Err(AppError::Config(format!(
+                    "system_prompt is too long (max {} characters)",
+                    MAX_SYSTEM_PROMPT_CHARS
+                )));
+            }
+        }
+
+        if self.ollama.stream_idle_timeout_secs == 0 {
+            return Err(AppError::Config(
+                "stream_idle_timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.ollama.max_history_messages == Some(0) {
+            return Err(AppError::Config(
+                "max_history_messages must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.ollama.stop.iter().any(String::is_empty) {
+            return Err(AppError::Config(
+                "ollama.stop entries cannot be empty".to_string(),
+            ));
+        }
+
+        if self.context_top_k == 0 {
+            return Err(AppError::Config(
+                "context_top_k must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.repeated_error_threshold == Some(0) {
+            return Err(AppError::Config(
+                "repeated_error_threshold must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.retry_command_max_retries == Some(0) {
+            return Err(AppError::Config(
+                "retry_command_max_retries must be greater than 0".to_string(),
+            ));
+        }
+
+        if !(1..=MAX_COMPLETIONS).contains(&self.completions) {
+            return Err(AppError::Config(format!(
+                "completions must be between 1 and {}",
+                MAX_COMPLETIONS
+            )));
+        }
+
+        if self.max_display_tokens_per_sec == Some(0) {
+            return Err(AppError::Config(
+                "max_display_tokens_per_sec must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.max_response_bytes == 0 {
+            return Err(AppError::Config(
+                "max_response_bytes must be greater than 0".to_string(),
+            ));
+        }
+
         if let Some(ref voice) = self.voice {
             if voice.model_path.is_empty() {
                 return Err(AppError::Config(
@@ -127,6 +992,42 @@ impl AppConfig {
                     "voice.max_duration_secs must be positive".to_string(),
                 ));
             }
+            if voice.audio_buffer_frames == Some(0) {
+                return Err(AppError::Config(
+                    "voice.audio_buffer_frames must be greater than 0".to_string(),
+                ));
+            }
+            if voice.min_recording_secs < 0.0 || voice.min_recording_secs >= voice.max_duration_secs
+            {
+                return Err(AppError::Config(
+                    "voice.min_recording_secs must be between 0 and voice.max_duration_secs"
+                        .to_string(),
+                ));
+            }
+            if voice.energy_threshold <= 0.0 || voice.energy_threshold >= 1.0 {
+                return Err(AppError::Config(format!(
+                    "voice.energy_threshold ({}) must be between 0 and 1, exclusive",
+                    voice.energy_threshold
+                )));
+            }
+            if voice.noise_floor_multiplier <= 1.0 {
+                return Err(AppError::Config(
+                    "voice.noise_floor_multiplier must be greater than 1".to_string(),
+                ));
+            }
+            if voice.silience_threshold_secs >= voice.max_duration_secs {
+                return Err(AppError::Config(format!(
+                    "voice.silence_threshold_secs ({}) must be less than voice.max_duration_secs ({})",
+                    voice.silience_threshold_secs, voice.max_duration_secs
+                )));
+            }
+            if voice.silience_threshold_secs > voice.max_duration_secs * 0.5 {
+                tracing::warn!(
+                    "voice.silence_threshold_secs ({}) is more than half of voice.max_duration_secs ({}), recordings will often run until max duration",
+                    voice.silience_threshold_secs,
+                    voice.max_duration_secs
+                );
+            }
         }
 
         Ok(())
@@ -138,7 +1039,7 @@ impl AppConfig {
 
     #[allow(dead_code)]
     pub fn load_or_default() -> Self {
-        match Self::load() {
+        match Self::load().and_then(|cfg| cfg.validate().map(|()| cfg)) {
             Ok(cfg) => cfg,
             Err(e) => {
                 tracing::warn!("Failed to load config: {}, using defaults", e);
@@ -151,13 +1052,190 @@ impl AppConfig {
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            provider: ProviderKind::default(),
+            max_response_bytes: default_max_response_bytes(),
+            prompt: default_prompt(),
+            idle_exit_secs: None,
+            output_fifo: None,
+            coalesce_window_ms: None,
+            input_lossy_utf8: false,
+            input_terminator: None,
+            input_history_path: default_input_history_path(),
+            blank_lines: BlankLinePolicies::default(),
+            abort_keyword: default_abort_keyword(),
+            keep_cancelled_in_history: default_keep_cancelled_in_history(),
+            truncation: Truncation::default(),
+            stream_granularity: StreamGranularity::default(),
+            output_format: OutputFormat::default(),
+            highlight_code: false,
+            show_error_codes: false,
+            max_display_tokens_per_sec: None,
+            show_prompt_estimate: false,
+            show_stats: false,
+            repeated_error_threshold: default_repeated_error_threshold(),
+            exit_on_repeated_errors: false,
+            retry_command_max_retries: None,
+            restart_on_stall: false,
+            stall_timeout_secs: default_stall_timeout_secs(),
+            max_stall_restarts: default_max_stall_restarts(),
+            show_reasoning: false,
+            response_prefix: None,
+            completions: default_completions(),
+            session_path: None,
+            session: None,
+            transcript_log: None,
+            context_top_k: default_context_top_k(),
+            model_switch: ModelSwitchPolicy::default(),
+            context_overflow: ContextOverflowPolicy::default(),
+            resend_after_reconnect: None,
             ollama: OllamaConfig {
-                base_url: "http://localhost:11434".to_string(),
+                base_url: default_base_url(),
                 model_name: "llama3.1:8b".to_string(),
                 timeout_secs: 30,
+                stream_idle_timeout_secs: default_stream_idle_timeout(),
                 max_retries: 3,
+                api_mode: ApiMode::default(),
+                stream: default_stream(),
+                max_history_messages: None,
+                system_prompt: None,
+                retry_budget: None,
+                retry_budget_window_secs: default_retry_budget_window(),
+                tls_ca_cert: None,
+                danger_accept_invalid_certs: false,
+                sampling: SamplingConfig::default(),
+                extra_options: HashMap::new(),
+                stop: Vec::new(),
+                model_preferences: Vec::new(),
+                auto_pull: false,
+                embedding_model: None,
+                health_path: default_health_path(),
+                health_method: HealthMethod::default(),
+                health_cache_ttl_secs: default_health_cache_ttl(),
+                log_request_bodies: false,
             },
+            openai: None,
+            fallback_providers: Vec::new(),
             voice: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn truncation(cut: CutPosition) -> Truncation {
+        Truncation {
+            marker: "~".to_string(),
+            cut,
+        }
+    }
+
+    fn blank_lines(toml: &str) -> BlankLinePolicies {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            blank_lines: BlankLinePolicies,
+        }
+        config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize::<Wrapper>()
+            .unwrap()
+            .blank_lines
+    }
+
+    #[test]
+    fn one_blank_line_policy_applies_to_every_mode() {
+        let policies = blank_lines(r#"blank_lines = "pass-through""#);
+        assert_eq!(policies.text, BlankLinePolicy::PassThrough);
+        assert_eq!(policies.stdin, BlankLinePolicy::PassThrough);
+        assert_eq!(policies.batch, BlankLinePolicy::PassThrough);
+    }
+
+    #[test]
+    fn blank_line_policies_can_differ_per_mode() {
+        let policies =
+            blank_lines(r#"blank_lines = { text = "pass-through", batch = "resubmit-last" }"#);
+        assert_eq!(policies.text, BlankLinePolicy::PassThrough);
+        assert_eq!(policies.stdin, BlankLinePolicy::Skip);
+        assert_eq!(policies.batch, BlankLinePolicy::ResubmitLast);
+    }
+
+    #[test]
+    fn fallback_without_a_model_keeps_the_primary_preferences() {
+        let primary = OllamaConfig {
+            model_name: String::new(),
+            model_preferences: vec!["llama3".to_string()],
+            ..AppConfig::default().ollama
+        };
+        let follower = FallbackProvider {
+            base_url: "http://backup:11434".to_string(),
+            model_name: None,
+        };
+        let pinned = FallbackProvider {
+            model_name: Some("mistral".to_string()),
+            ..follower.clone()
+        };
+
+        let config = follower.ollama_config(&primary);
+        assert_eq!(config.base_url, "http://backup:11434");
+        assert_eq!(config.model_preferences, primary.model_preferences);
+        assert!(follower.follows_primary_model());
+
+        let config = pinned.ollama_config(&primary);
+        assert_eq!(config.model_name, "mistral");
+        assert!(config.model_preferences.is_empty());
+        assert!(!pinned.follows_primary_model());
+    }
+
+    #[test]
+    fn short_text_is_unchanged() {
+        for cut in [CutPosition::Head, CutPosition::Tail, CutPosition::Middle] {
+            assert_eq!(truncation(cut).apply("hello", 5), "hello");
+        }
+    }
+
+    #[test]
+    fn tail_keeps_the_start() {
+        assert_eq!(
+            truncation(CutPosition::Tail).apply("hello world", 5),
+            "hello~"
+        );
+    }
+
+    #[test]
+    fn head_keeps_the_end() {
+        assert_eq!(
+            truncation(CutPosition::Head).apply("hello world", 5),
+            "~world"
+        );
+    }
+
+    #[test]
+    fn middle_keeps_both_ends() {
+        assert_eq!(
+            truncation(CutPosition::Middle).apply("hello world", 6),
+            "hel~rld"
+        );
+    }
+
+    #[test]
+    fn tail_cut_backs_off_to_a_char_boundary() {
+        // Byte 2 falls inside "ñ".
+        assert_eq!(truncation(CutPosition::Tail).apply("añb", 2), "a~");
+        assert_eq!(truncation(CutPosition::Tail).apply("🙂🙂", 5), "🙂~");
+    }
+
+    #[test]
+    fn head_cut_moves_forward_to_a_char_boundary() {
+        assert_eq!(truncation(CutPosition::Head).apply("añb", 2), "~b");
+        assert_eq!(truncation(CutPosition::Head).apply("🙂🙂", 5), "~🙂");
+    }
+
+    #[test]
+    fn middle_cut_lands_on_char_boundaries() {
+        // Both halves would split an "ñ".
+        assert_eq!(truncation(CutPosition::Middle).apply("aññb", 3), "a~b");
+    }
+}
@@ -47,8 +47,21 @@ pub enum AppError {
     #[error("Retry limit exceeded: {attempts} attempts")]
     RetryExhausted { attempts: u32 },
 
+    #[error("Retry budget exhausted ({retries} in {window_secs}s), retries paused: {last_error}")]
+    RetryBudgetExhausted {
+        retries: u32,
+        window_secs: u64,
+        last_error: String,
+    },
+
     #[error("Operation cancelled")]
     Cancelled,
+
+    #[error("{provider} does not support {capability}")]
+    Unsupported {
+        provider: String,
+        capability: &'static str,
+    },
 }
 
 impl AppError {
@@ -82,6 +95,38 @@ impl AppError {
             Self::Http(_) | Self::Timeout { .. } | Self::ServiceUnavailable(_)
         )
     }
+
+    /// Errors the session cannot recover from by taking the next input.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::Config(_) | Self::NoAudioDevice | Self::Output(_)
+        )
+    }
+
+    /// Stable identifier for the error kind, for callers that branch on it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Config(_) => "config",
+            Self::Io(_) => "io",
+            Self::Input(_) => "input",
+            Self::Output(_) => "output",
+            Self::Audio(_) => "audio",
+            Self::SpeechRecognition(_) => "speech_recognition",
+            Self::NoAudioDevice => "no_audio_device",
+            Self::Http(_) => "http",
+            Self::ServiceUnavailable(_) => "service_unavailable",
+            Self::Timeout { .. } => "timeout",
+            Self::Json(_) => "json",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::Llm(_) => "llm",
+            Self::StreamEnded => "stream_ended",
+            Self::RetryExhausted { .. } => "retry_exhausted",
+            Self::RetryBudgetExhausted { .. } => "retry_budget_exhausted",
+            Self::Cancelled => "cancelled",
+            Self::Unsupported { .. } => "unsupported",
+        }
+    }
 }
 
 impl From<config::ConfigError> for AppError {
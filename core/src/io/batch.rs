@@ -0,0 +1,74 @@
+use crate::config::BlankLinePolicy;
+use crate::error::{AppError, Result};
+use crate::io::input::BlankLineFilter;
+use crate::io::InputSource;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+
+/// Prompts read up front from a file, separated by lines equal to a delimiter.
+pub struct BatchInput {
+    prompts: VecDeque<String>,
+    total: usize,
+}
+
+impl BatchInput {
+    pub fn from_file(path: &str, delimiter: &str, blank_lines: BlankLinePolicy) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Input(format!("read batch file {} failed: {}", path, e)))?;
+
+        let prompts = Self::split_prompts(&content, delimiter, blank_lines);
+        if prompts.iter().all(|prompt| prompt.is_empty()) {
+            return Err(AppError::Input(format!(
+                "batch file {} has no prompts",
+                path
+            )));
+        }
+
+        tracing::info!("Loaded {} prompts from {}", prompts.len(), path);
+        Ok(Self {
+            total: prompts.len(),
+            prompts,
+        })
+    }
+
+    /// Empty prompts go through the blank line policy; one after the last
+    /// delimiter is always dropped.
+    fn split_prompts(
+        content: &str,
+        delimiter: &str,
+        blank_lines: BlankLinePolicy,
+    ) -> VecDeque<String> {
+        let mut blocks = Vec::new();
+        let mut current = String::new();
+
+        for line in content.lines() {
+            if line.trim() == delimiter {
+                blocks.push(current.trim().to_string());
+                current.clear();
+            } else {
+                current.push_str(line);
+                current.push('\n');
+            }
+        }
+        if !current.trim().is_empty() {
+            blocks.push(current.trim().to_string());
+        }
+
+        let mut filter = BlankLineFilter::new(blank_lines);
+        blocks
+            .into_iter()
+            .filter_map(|prompt| filter.apply(prompt))
+            .collect()
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+#[async_trait]
+impl InputSource for BatchInput {
+    async fn next(&mut self) -> Result<Option<String>> {
+        Ok(self.prompts.pop_front())
+    }
+}
@@ -0,0 +1,256 @@
+use crate::config::AppConfig;
+use crate::error::{AppError, Result};
+use crate::io::input::{BlankLineFilter, LineBlock};
+use crate::io::InputSource;
+use async_trait::async_trait;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::PathBuf;
+use std::sync::mpsc as std_mpsc;
+use tokio::sync::mpsc;
+
+// Shown while a multi-line prompt is still open.
+const CONTINUATION_PROMPT: &str = "... ";
+
+type LineResult = Result<Option<String>>;
+
+/// Which prompt the outstanding read was started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PendingRead {
+    Prompted,
+    /// Started while a response streams, so nothing is drawn over it.
+    Quiet,
+}
+
+/// Terminal input with line editing, history navigation and Ctrl+R search.
+///
+/// rustyline blocks, so a dedicated thread owns the editor and reads one line
+/// per request, each carrying the prompt to show. A request stays outstanding
+/// when `next` is cancelled, which keeps `next` cancel-safe like `TextInput`.
+pub struct RichTextInput {
+    prompt: String,
+    requests: std_mpsc::Sender<String>,
+    lines: mpsc::UnboundedReceiver<LineResult>,
+    pending: Option<PendingRead>,
+    terminal: Option<SavedTerminal>,
+}
+
+/// Terminal settings from before the editor started. A read still outstanding
+/// at shutdown never returns, so rustyline cannot take the terminal out of raw
+/// mode itself.
+struct SavedTerminal {
+    #[cfg(unix)]
+    termios: libc::termios,
+}
+
+impl SavedTerminal {
+    #[cfg(unix)]
+    fn capture() -> Option<Self> {
+        let mut termios = std::mem::MaybeUninit::uninit();
+        // SAFETY: tcgetattr initializes `termios` when it returns 0.
+        let status = unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) };
+        (status == 0).then(|| Self {
+            termios: unsafe { termios.assume_init() },
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn capture() -> Option<Self> {
+        None
+    }
+
+    fn restore(&self) {
+        #[cfg(unix)]
+        // SAFETY: `termios` came from tcgetattr on the same descriptor.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.termios);
+        }
+    }
+}
+
+/// Settings the editor thread applies to each line it reads.
+struct LineReader {
+    editor: DefaultEditor,
+    history_path: Option<PathBuf>,
+    block: Option<LineBlock>,
+    blank_lines: BlankLineFilter,
+    lossy_utf8: bool,
+}
+
+impl RichTextInput {
+    pub fn new(cfg: &AppConfig) -> Result<Self> {
+        tracing::debug!("Initializing line editor input");
+
+        let mut editor = DefaultEditor::new()
+            .map_err(|e| AppError::Input(format!("line editor unavailable: {}", e)))?;
+        let history_path = cfg
+            .input_history_path
+            .clone()
+            .filter(|path| !path.as_os_str().is_empty());
+        if let Some(ref path) = history_path {
+            if let Err(e) = editor.load_history(path) {
+                tracing::debug!("No input history loaded from {}: {}", path.display(), e);
+            }
+        }
+
+        let terminal = SavedTerminal::capture();
+        let (requests, request_rx) = std_mpsc::channel();
+        let (line_tx, lines) = mpsc::unbounded_channel();
+        let reader = LineReader {
+            editor,
+            history_path,
+            block: cfg.input_terminator.as_deref().map(LineBlock::new),
+            blank_lines: BlankLineFilter::new(cfg.blank_lines.text),
+            lossy_utf8: cfg.input_lossy_utf8,
+        };
+        std::thread::Builder::new()
+            .name("line-editor".to_string())
+            .spawn(move || reader.run(request_rx, line_tx))?;
+
+        Ok(Self {
+            prompt: cfg.prompt.clone(),
+            requests,
+            lines,
+            pending: None,
+            terminal,
+        })
+    }
+
+    async fn read(&mut self, kind: PendingRead) -> Result<Option<String>> {
+        if self.pending.is_none() {
+            let prompt = match kind {
+                PendingRead::Prompted => self.prompt.clone(),
+                PendingRead::Quiet => String::new(),
+            };
+            self.requests
+                .send(prompt)
+                .map_err(|_| AppError::Input("line editor stopped".to_string()))?;
+            self.pending = Some(kind);
+        }
+
+        let line = self
+            .lines
+            .recv()
+            .await
+            .ok_or_else(|| AppError::Input("line editor stopped".to_string()))?;
+        self.pending = None;
+        line
+    }
+}
+
+impl LineReader {
+    fn run(
+        mut self,
+        requests: std_mpsc::Receiver<String>,
+        lines: mpsc::UnboundedSender<LineResult>,
+    ) {
+        while let Ok(prompt) = requests.recv() {
+            if lines.send(self.read_line(&prompt)).is_err() {
+                break;
+            }
+        }
+        tracing::debug!("Line editor thread stopped");
+    }
+
+    /// Reads until there is a line to submit under the blank line policy.
+    fn read_line(&mut self, prompt: &str) -> LineResult {
+        loop {
+            let line = match self.read_entry(prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Eof) => return Ok(None),
+                // The editor holds the terminal in raw mode, so Ctrl+C arrives here, not as a signal.
+                Err(ReadlineError::Interrupted) => return Err(AppError::Cancelled),
+                Err(ReadlineError::Io(ref e)) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    if self.lossy_utf8 {
+                        tracing::warn!("Ignoring invalid UTF-8 typed at the terminal");
+                        continue;
+                    }
+                    return Err(AppError::Input(
+                        "terminal input is not valid UTF-8; set input_lossy_utf8 = true to ignore invalid bytes"
+                            .to_string(),
+                    ));
+                }
+                Err(e) => return Err(AppError::Input(format!("read line failed: {}", e))),
+            };
+
+            if !line.trim().is_empty() {
+                self.remember(&line);
+            }
+            if self.block.is_some() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Ok(Some(line));
+            }
+            if let Some(line) = self.blank_lines.apply(line) {
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    fn remember(&mut self, line: &str) {
+        let _ = self.editor.add_history_entry(line);
+        if let Some(ref path) = self.history_path {
+            if let Err(e) = self.editor.save_history(path) {
+                tracing::warn!("Save input history failed: {}", e);
+            }
+        }
+    }
+
+    /// Reads one line, or with a terminator, every line up to it.
+    fn read_entry(&mut self, prompt: &str) -> std::result::Result<String, ReadlineError> {
+        let Some(ref mut block) = self.block else {
+            return self.editor.readline(prompt);
+        };
+
+        loop {
+            let prompt = if block.is_empty() {
+                prompt
+            } else {
+                CONTINUATION_PROMPT
+            };
+            match self.editor.readline(prompt) {
+                Ok(line) => {
+                    if let Some(text) = block.push(&line) {
+                        return Ok(text);
+                    }
+                }
+                Err(ReadlineError::Eof) if !block.is_empty() => return Ok(block.take()),
+                Err(e) => {
+                    block.take();
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl InputSource for RichTextInput {
+    async fn next(&mut self) -> Result<Option<String>> {
+        self.read(PendingRead::Prompted).await
+    }
+
+    async fn next_while_streaming(&mut self) -> Result<Option<String>> {
+        self.read(PendingRead::Quiet).await
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
+    /// A read started while streaming has no prompt, so the runtime prints one.
+    fn shows_prompt(&self) -> bool {
+        self.pending != Some(PendingRead::Quiet)
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        if self.pending.is_some() {
+            if let Some(ref terminal) = self.terminal {
+                tracing::debug!("Restoring the terminal left in raw mode by an unfinished read");
+                terminal.restore();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use crate::error::{AppError, Result};
+use crate::io::OutputSink;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+pub struct FifoOutput {
+    path: PathBuf,
+    writer: Option<File>,
+}
+
+impl FifoOutput {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Err(AppError::Output(format!(
+                "fifo does not exist: {} (create it with mkfifo)",
+                path.display()
+            )));
+        }
+
+        tracing::info!("Waiting for a reader on fifo: {}", path.display());
+        let writer = OpenOptions::new().write(true).open(path).await?;
+        tracing::info!("Fifo reader connected");
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Some(writer),
+        })
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        let result = writer.write_all(bytes).await;
+        self.handle_io_result(result)
+    }
+
+    fn handle_io_result(&mut self, result: std::io::Result<()>) -> Result<()> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                tracing::warn!(
+                    "Fifo reader disconnected: {}, dropping further output",
+                    self.path.display()
+                );
+                self.writer = None;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl OutputSink for FifoOutput {
+    async fn emit(&mut self, text: &str) -> Result<()> {
+        self.write(text.as_bytes()).await?;
+        self.write(b"\n").await?;
+        self.flush().await
+    }
+
+    async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+        self.write(chunk.as_bytes()).await
+    }
+
+    async fn emit_error(&mut self, error: &str) -> Result<()> {
+        self.write(b"Error: ").await?;
+        self.write(error.as_bytes()).await?;
+        self.write(b"\n").await?;
+        self.flush().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        let result = writer.flush().await;
+        self.handle_io_result(result)
+    }
+}
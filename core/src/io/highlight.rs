@@ -0,0 +1,174 @@
+use crate::error::{AppError, Result};
+use crate::io::{OutputSink, ResponseStats};
+use async_trait::async_trait;
+use std::io::IsTerminal;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+const FENCE: &str = "```";
+const THEME: &str = "base16-ocean.dark";
+const RESET: &str = "\x1b[0m";
+
+/// Streams text through unchanged, except fenced code blocks, which are held
+/// until the closing fence and then syntax-highlighted by their language tag.
+pub struct HighlightOutput<S: OutputSink> {
+    inner: S,
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    /// The current line, held back only while it may still turn into a fence.
+    line: String,
+    /// Language tag and contents of the open code block, if any.
+    block: Option<(String, String)>,
+    enabled: bool,
+}
+
+impl<S: OutputSink> HighlightOutput<S> {
+    pub fn new(inner: S) -> Self {
+        let mut themes = ThemeSet::load_defaults();
+        let theme = themes.themes.remove(THEME).unwrap_or_default();
+
+        Self {
+            inner,
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+            line: String::new(),
+            block: None,
+            enabled: std::io::stdout().is_terminal(),
+        }
+    }
+
+    fn may_be_fence(line: &str) -> bool {
+        let trimmed = line.trim_start();
+        trimmed.starts_with(FENCE) || FENCE.starts_with(trimmed)
+    }
+
+    /// Feeds one chunk through the fence tracker, returning what can be shown now.
+    fn process(&mut self, chunk: &str) -> String {
+        let mut out = String::new();
+
+        for c in chunk.chars() {
+            self.line.push(c);
+            let line_done = c == '\n';
+
+            match self.block {
+                None if line_done => {
+                    let line = std::mem::take(&mut self.line);
+                    if let Some(lang) = line.trim().strip_prefix(FENCE) {
+                        self.block = Some((lang.trim().to_string(), String::new()));
+                    }
+                    out.push_str(&line);
+                }
+                None if !Self::may_be_fence(&self.line) => {
+                    out.push_str(&std::mem::take(&mut self.line));
+                }
+                None => {}
+                Some(_) if line_done => {
+                    let line = std::mem::take(&mut self.line);
+                    if line.trim().starts_with(FENCE) {
+                        if let Some((lang, code)) = self.block.take() {
+                            out.push_str(&self.highlight(&lang, &code));
+                        }
+                        out.push_str(&line);
+                    } else if let Some((_, code)) = self.block.as_mut() {
+                        code.push_str(&line);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        out
+    }
+
+    fn highlight(&self, lang: &str, code: &str) -> String {
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_token(lang)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+
+        let mut out = String::with_capacity(code.len() * 2);
+        for line in LinesWithEndings::from(code) {
+            match highlighter.highlight_line(line, &self.syntaxes) {
+                Ok(ranges) => out.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+                Err(e) => {
+                    tracing::debug!("highlighting {} failed: {}", lang, e);
+                    out.push_str(line);
+                }
+            }
+        }
+        out.push_str(RESET);
+
+        out
+    }
+
+    /// Releases anything held back; an unterminated block is shown unhighlighted.
+    async fn drain(&mut self) -> Result<()> {
+        let mut out = String::new();
+        if let Some((_, code)) = self.block.take() {
+            out.push_str(&code);
+        }
+        out.push_str(&std::mem::take(&mut self.line));
+
+        if out.is_empty() {
+            return Ok(());
+        }
+        self.inner.emit_chunk(&out).await
+    }
+}
+
+#[async_trait]
+impl<S: OutputSink> OutputSink for HighlightOutput<S> {
+    async fn emit(&mut self, text: &str) -> Result<()> {
+        self.drain().await?;
+        self.inner.emit(text).await
+    }
+
+    async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+        if !self.enabled {
+            return self.inner.emit_chunk(chunk).await;
+        }
+
+        let out = self.process(chunk);
+        if out.is_empty() {
+            return Ok(());
+        }
+        self.inner.emit_chunk(&out).await
+    }
+
+    async fn emit_error(&mut self, error: &str) -> Result<()> {
+        self.inner.emit_error(error).await
+    }
+
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        self.inner.emit_app_error(error).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn finish_response(&mut self) -> Result<()> {
+        self.drain().await?;
+        self.inner.finish_response().await
+    }
+
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        self.drain().await?;
+        self.inner.emit_reasoning(chunk).await
+    }
+
+    async fn begin_turn(&mut self, prompt: &str, model: &str) -> Result<()> {
+        self.inner.begin_turn(prompt, model).await
+    }
+
+    fn record_stats(&mut self, stats: ResponseStats) {
+        self.inner.record_stats(stats)
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.inner.is_terminal()
+    }
+}
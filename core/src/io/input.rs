@@ -1,46 +1,224 @@
-use crate::error::Result;
+use crate::config::BlankLinePolicy;
+use crate::error::{AppError, Result};
 use async_trait::async_trait;
-use tokio::io::{self, AsyncBufReadExt};
+use std::io::IsTerminal;
+use tokio::io::{self, AsyncBufRead, AsyncBufReadExt};
 
 #[async_trait]
 pub trait InputSource: Send {
     async fn next(&mut self) -> Result<Option<String>>;
+
+    /// Whether a human is typing at a terminal, so prompts are worth showing.
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    /// Whether the source draws its own prompt, so the runtime must not print one.
+    fn shows_prompt(&self) -> bool {
+        false
+    }
+
+    /// Reads a line typed while a response streams, such as the abort keyword.
+    /// Sources that draw their own prompt read it without one.
+    async fn next_while_streaming(&mut self) -> Result<Option<String>> {
+        self.next().await
+    }
+
+    /// Turns translating speech into English on or off for later turns;
+    /// returns false for sources that do not transcribe speech.
+    fn set_translate(&mut self, _translate: bool) -> bool {
+        false
+    }
+
+    /// Releases resources on shutdown; called once by the runtime before exit.
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects lines into one prompt until a terminator line. An empty
+/// terminator ends the block at the first blank line after some content.
+pub struct LineBlock {
+    terminator: String,
+    lines: Vec<String>,
+}
+
+impl LineBlock {
+    pub fn new(terminator: &str) -> Self {
+        Self {
+            terminator: terminator.trim().to_string(),
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Adds a line, returning the finished block when `line` is the terminator.
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim() == self.terminator {
+            if self.terminator.is_empty() && self.lines.is_empty() {
+                return None;
+            }
+            return Some(self.take());
+        }
+
+        self.lines.push(line.to_string());
+        None
+    }
+
+    /// Returns whatever was collected, e.g. when input ends mid-block. Blank
+    /// lines around the block are dropped; indentation of its first line is kept.
+    pub fn take(&mut self) -> String {
+        let text = std::mem::take(&mut self.lines).join("\n");
+        let content_start = text.len() - text.trim_start().len();
+        let line_start = text[..content_start].rfind('\n').map_or(0, |i| i + 1);
+        text[line_start..].trim_end().to_string()
+    }
+}
+
+/// Applies a `BlankLinePolicy` to single-line input, remembering the last line.
+pub struct BlankLineFilter {
+    policy: BlankLinePolicy,
+    last_line: Option<String>,
+}
+
+impl BlankLineFilter {
+    pub fn new(policy: BlankLinePolicy) -> Self {
+        Self {
+            policy,
+            last_line: None,
+        }
+    }
+
+    /// Returns the line to submit, or `None` when another line should be read.
+    pub fn apply(&mut self, line: String) -> Option<String> {
+        if !line.trim().is_empty() {
+            self.last_line = Some(line.clone());
+            return Some(line);
+        }
+
+        match self.policy {
+            BlankLinePolicy::Skip => {
+                tracing::trace!("Skipping empty line");
+                None
+            }
+            BlankLinePolicy::ResubmitLast => {
+                let last = self.last_line.clone();
+                if last.is_some() {
+                    tracing::trace!("Resubmitting last line");
+                }
+                last
+            }
+            BlankLinePolicy::PassThrough => Some(line),
+        }
+    }
 }
 
-pub struct TextInput {
-    reader: io::BufReader<io::Stdin>,
+pub struct TextInput<R = io::BufReader<io::Stdin>> {
+    reader: R,
+    buffer: Vec<u8>,
+    interactive: bool,
+    lossy_utf8: bool,
+    blank_lines: BlankLineFilter,
+    block: Option<LineBlock>,
 }
 
 impl TextInput {
     pub fn new() -> Self {
         tracing::debug!("Initializing stdin input");
+        Self::from_reader(
+            io::BufReader::new(io::stdin()),
+            std::io::stdin().is_terminal(),
+        )
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send> TextInput<R> {
+    fn from_reader(reader: R, interactive: bool) -> Self {
         Self {
-            reader: io::BufReader::new(io::stdin()),
+            reader,
+            buffer: Vec::new(),
+            interactive,
+            lossy_utf8: false,
+            blank_lines: BlankLineFilter::new(BlankLinePolicy::default()),
+            block: None,
+        }
+    }
+
+    /// Reads multi-line prompts ending at `terminator` instead of one line each.
+    pub fn terminator(mut self, terminator: Option<&str>) -> Self {
+        self.block = terminator.map(LineBlock::new);
+        self
+    }
+
+    pub fn lossy_utf8(mut self, enabled: bool) -> Self {
+        self.lossy_utf8 = enabled;
+        self
+    }
+
+    pub fn blank_lines(mut self, policy: BlankLinePolicy) -> Self {
+        self.blank_lines = BlankLineFilter::new(policy);
+        self
+    }
+
+    fn decode(&self, raw: Vec<u8>) -> Result<String> {
+        match String::from_utf8(raw) {
+            Ok(line) => Ok(line),
+            Err(e) if self.lossy_utf8 => {
+                tracing::warn!("Replacing invalid UTF-8 bytes in input");
+                Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+            }
+            Err(e) => Err(AppError::Input(format!(
+                "stdin is not valid UTF-8 (invalid byte at offset {}); set input_lossy_utf8 = true to replace invalid bytes",
+                e.utf8_error().valid_up_to()
+            ))),
         }
     }
 }
 
 #[async_trait]
-impl InputSource for TextInput {
+impl<R: AsyncBufRead + Unpin + Send> InputSource for TextInput<R> {
     async fn next(&mut self) -> Result<Option<String>> {
-        let mut line = String::new();
+        loop {
+            // read_until keeps partial data in self.buffer, so a cancelled read loses nothing.
+            let byte_read = self.reader.read_until(b'\n', &mut self.buffer).await?;
 
-        let byte_read = self.reader.read_line(&mut line).await?;
+            if byte_read == 0 && self.buffer.is_empty() {
+                if let Some(ref mut block) = self.block {
+                    if !block.is_empty() {
+                        tracing::debug!("Reached EOF inside a block, submitting it");
+                        return Ok(Some(block.take()));
+                    }
+                }
+                tracing::debug!("Reached EOF");
+                return Ok(None);
+            }
 
-        if byte_read == 0 {
-            tracing::debug!("Reached EOF");
-            return Ok(None);
-        }
-
-        let trimmed = line.trim().to_string();
+            let raw = std::mem::take(&mut self.buffer);
+            let line = self.decode(raw)?;
+            if let Some(ref mut block) = self.block {
+                match block.push(&line) {
+                    Some(text) if !text.is_empty() => {
+                        tracing::trace!("Read input block: {} chars", text.len());
+                        return Ok(Some(text));
+                    }
+                    _ => continue,
+                }
+            }
+            let Some(line) = self.blank_lines.apply(line.trim().to_string()) else {
+                continue;
+            };
 
-        if trimmed.is_empty() {
-            tracing::trace!("Skipping empty line");
-            return self.next().await;
+            tracing::trace!("Read input: {} chars", line.len());
+            return Ok(Some(line));
         }
+    }
 
-        tracing::trace!("Read input: {} chars", trimmed.len());
-        Ok(Some(trimmed))
+    fn is_interactive(&self) -> bool {
+        self.interactive
     }
 }
 
@@ -49,3 +227,60 @@ impl Default for TextInput {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(bytes: &'static [u8]) -> TextInput<&'static [u8]> {
+        TextInput::from_reader(bytes, false)
+    }
+
+    #[tokio::test]
+    async fn blank_line_policies() {
+        let lines = b"one\n\ntwo\n";
+        let cases = [
+            (BlankLinePolicy::Skip, vec!["one", "two"]),
+            (BlankLinePolicy::ResubmitLast, vec!["one", "one", "two"]),
+            (BlankLinePolicy::PassThrough, vec!["one", "", "two"]),
+        ];
+
+        for (policy, expected) in cases {
+            let mut input = input(lines).blank_lines(policy);
+            for line in expected {
+                assert_eq!(input.next().await.unwrap().as_deref(), Some(line));
+            }
+            assert_eq!(input.next().await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_utf8_by_default() {
+        let mut input = input(b"caf\xe9\n");
+
+        let err = input.next().await.unwrap_err();
+
+        assert!(matches!(err, AppError::Input(ref msg) if msg.contains("offset 3")));
+    }
+
+    #[tokio::test]
+    async fn replaces_invalid_utf8_when_lossy() {
+        let mut input = input(b"caf\xe9\nnext\n").lossy_utf8(true);
+
+        assert_eq!(input.next().await.unwrap().as_deref(), Some("caf\u{fffd}"));
+        assert_eq!(input.next().await.unwrap().as_deref(), Some("next"));
+        assert_eq!(input.next().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn replaces_invalid_utf8_in_an_unterminated_last_line() {
+        let mut input = input(b"ok\n\xff\xfe").lossy_utf8(true);
+
+        assert_eq!(input.next().await.unwrap().as_deref(), Some("ok"));
+        assert_eq!(
+            input.next().await.unwrap().as_deref(),
+            Some("\u{fffd}\u{fffd}")
+        );
+        assert_eq!(input.next().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::error::{AppError, Result};
+use crate::io::{OutputSink, ResponseStats};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{self, AsyncWriteExt};
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Event<'a> {
+    /// The start of a turn: the prompt as sent, the model it goes to and
+    /// milliseconds since the Unix epoch.
+    Prompt {
+        text: &'a str,
+        model: &'a str,
+        ts: u64,
+    },
+    /// A piece of a streamed response.
+    Chunk { text: &'a str },
+    /// A piece of the model's reasoning, sent only when it is shown.
+    Reasoning { text: &'a str },
+    /// A complete status line, such as the echoed prompt or a command result.
+    Message { text: &'a str },
+    Error {
+        message: &'a str,
+        /// Set when the error came from an `AppError`; see `AppError::code`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<&'static str>,
+    },
+    /// The end of a turn; every stats field is present, null when unknown.
+    Done { stats: ResponseStats },
+}
+
+/// Writes one JSON object per line to stdout for programs consuming the stream.
+pub struct JsonOutput {
+    stdout: io::Stdout,
+    stats: ResponseStats,
+}
+
+impl JsonOutput {
+    pub fn new() -> Self {
+        tracing::debug!("Initializing JSON lines output");
+        Self {
+            stdout: io::stdout(),
+            stats: ResponseStats::default(),
+        }
+    }
+
+    async fn write_event(&mut self, event: Event<'_>) -> Result<()> {
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        self.stdout.write_all(&line).await?;
+        self.stdout.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputSink for JsonOutput {
+    async fn emit(&mut self, text: &str) -> Result<()> {
+        // Blank lines only space out the terminal view.
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.write_event(Event::Message { text }).await
+    }
+
+    async fn begin_turn(&mut self, prompt: &str, model: &str) -> Result<()> {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        self.write_event(Event::Prompt {
+            text: prompt,
+            model,
+            ts,
+        })
+        .await
+    }
+
+    async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.write_event(Event::Chunk { text: chunk }).await
+    }
+
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.write_event(Event::Reasoning { text: chunk }).await
+    }
+
+    async fn emit_error(&mut self, error: &str) -> Result<()> {
+        self.write_event(Event::Error {
+            message: error,
+            code: None,
+        })
+        .await
+    }
+
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        self.write_event(Event::Error {
+            message: &error.to_string(),
+            code: Some(error.code()),
+        })
+        .await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.stdout.flush().await?;
+        Ok(())
+    }
+
+    async fn finish_response(&mut self) -> Result<()> {
+        let stats = std::mem::take(&mut self.stats);
+        self.write_event(Event::Done { stats }).await
+    }
+
+    fn record_stats(&mut self, stats: ResponseStats) {
+        self.stats = stats;
+    }
+}
+
+impl Default for JsonOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::error::{AppError, Result};
+use crate::io::{OutputSink, ResponseStats};
+use async_trait::async_trait;
+use std::io::IsTerminal;
+
+const BOLD: &str = "\x1b[1m";
+const UNDERLINE: &str = "\x1b[4m";
+const CODE: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Buffers streamed Markdown and renders it with ANSI styles when flushed.
+/// Styling is skipped when stdout is not a terminal.
+pub struct MarkdownOutput<S: OutputSink> {
+    inner: S,
+    pending: String,
+    in_code_block: bool,
+    styled: bool,
+}
+
+impl<S: OutputSink> MarkdownOutput<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            pending: String::new(),
+            in_code_block: false,
+            styled: std::io::stdout().is_terminal(),
+        }
+    }
+
+    async fn render_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let text = std::mem::take(&mut self.pending);
+        let rendered = if self.styled {
+            self.render(&text)
+        } else {
+            text
+        };
+        self.inner.emit_chunk(&rendered).await
+    }
+
+    fn render(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+
+        for line in text.split_inclusive('\n') {
+            let (body, newline) = match line.strip_suffix('\n') {
+                Some(body) => (body, "\n"),
+                None => (line, ""),
+            };
+            out.push_str(&self.render_line(body));
+            out.push_str(newline);
+        }
+
+        out
+    }
+
+    fn render_line(&mut self, line: &str) -> String {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") {
+            self.in_code_block = !self.in_code_block;
+            return format!("{}{}{}", DIM, line, RESET);
+        }
+
+        if self.in_code_block {
+            return format!("{}{}{}", CODE, line, RESET);
+        }
+
+        if let Some(heading) = Self::heading_text(trimmed) {
+            return format!("{}{}{}{}", BOLD, UNDERLINE, heading, RESET);
+        }
+
+        for marker in ["- ", "* ", "+ "] {
+            if let Some(item) = trimmed.strip_prefix(marker) {
+                let indent = &line[..line.len() - trimmed.len()];
+                return format!("{}• {}", indent, Self::render_inline(item));
+            }
+        }
+
+        Self::render_inline(line)
+    }
+
+    fn heading_text(line: &str) -> Option<&str> {
+        let level = line.chars().take_while(|c| *c == '#').count();
+        if level == 0 || level > 6 {
+            return None;
+        }
+        line[level..].strip_prefix(' ').map(str::trim)
+    }
+
+    /// Styles `**bold**` and `` `code` `` spans; unmatched markers are left as typed.
+    fn render_inline(text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+
+        loop {
+            let bold = rest.find("**");
+            let code = rest.find('`');
+            let (start, marker, style) = match (bold, code) {
+                (Some(b), Some(c)) if c < b => (c, "`", CODE),
+                (Some(b), _) => (b, "**", BOLD),
+                (None, Some(c)) => (c, "`", CODE),
+                (None, None) => break,
+            };
+
+            let after = &rest[start + marker.len()..];
+            let Some(end) = after.find(marker) else {
+                break;
+            };
+
+            out.push_str(&rest[..start]);
+            out.push_str(style);
+            out.push_str(&after[..end]);
+            out.push_str(RESET);
+            rest = &after[end + marker.len()..];
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
+
+#[async_trait]
+impl<S: OutputSink> OutputSink for MarkdownOutput<S> {
+    async fn emit(&mut self, text: &str) -> Result<()> {
+        self.render_pending().await?;
+        self.inner.emit(text).await
+    }
+
+    async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+        self.pending.push_str(chunk);
+        Ok(())
+    }
+
+    async fn emit_error(&mut self, error: &str) -> Result<()> {
+        self.render_pending().await?;
+        self.inner.emit_error(error).await
+    }
+
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        self.render_pending().await?;
+        self.inner.emit_app_error(error).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.render_pending().await?;
+        self.inner.flush().await
+    }
+
+    async fn finish_response(&mut self) -> Result<()> {
+        self.render_pending().await?;
+        self.in_code_block = false;
+        self.inner.finish_response().await
+    }
+
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        self.render_pending().await?;
+        self.inner.emit_reasoning(chunk).await
+    }
+
+    async fn begin_turn(&mut self, prompt: &str, model: &str) -> Result<()> {
+        self.inner.begin_turn(prompt, model).await
+    }
+
+    fn record_stats(&mut self, stats: ResponseStats) {
+        self.inner.record_stats(stats)
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.inner.is_terminal()
+    }
+}
@@ -1,7 +1,23 @@
+pub mod batch;
+pub mod editor;
+pub mod fifo;
+pub mod highlight;
 pub mod input;
+pub mod json;
+pub mod markdown;
 pub mod output;
+pub mod spinner;
+pub mod tts;
 pub mod voice;
 
+pub use batch::BatchInput;
+pub use editor::RichTextInput;
+pub use fifo::FifoOutput;
+pub use highlight::HighlightOutput;
 pub use input::{InputSource, TextInput};
-pub use output::{OutputSink, TextOutput};
+pub use json::JsonOutput;
+pub use markdown::MarkdownOutput;
+pub use output::{OutputSink, ResponseStats, TextOutput};
+pub use spinner::Spinner;
+pub use tts::VoiceOutput;
 pub use voice::VoiceInput;
@@ -1,18 +1,149 @@
-use crate::error::Result;
+use crate::error::{AppError, Result};
 use async_trait::async_trait;
+use serde::Serialize;
+use std::io::IsTerminal;
 use tokio::io::{self, AsyncWriteExt};
 
+/// How a response went, reported to sinks before it finishes. Fields are
+/// `None` when unknown, e.g. token counts from a backend that sends none.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ResponseStats {
+    pub time_to_first_token_ms: Option<u64>,
+    pub duration_ms: Option<u64>,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+}
+
 #[async_trait]
 pub trait OutputSink: Send {
     async fn emit(&mut self, text: &str) -> Result<()>;
     async fn emit_chunk(&mut self, chunk: &str) -> Result<()>;
     async fn emit_error(&mut self, error: &str) -> Result<()>;
     async fn flush(&mut self) -> Result<()>;
+
+    /// Reports `error`; sinks with structured output can also record its code.
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        self.emit_error(&error.to_string()).await
+    }
+
+    /// A piece of a model's `<think>` reasoning, shown apart from the answer.
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        self.emit_chunk(chunk).await
+    }
+
+    /// Starts a turn by echoing the prompt; structured sinks also note the model.
+    async fn begin_turn(&mut self, prompt: &str, _model: &str) -> Result<()> {
+        self.emit(&format!("You: {}", prompt)).await?;
+        self.emit("").await
+    }
+
+    /// Notes how the current response went; sinks with structured output
+    /// report it when the response finishes.
+    fn record_stats(&mut self, _stats: ResponseStats) {}
+
+    /// Ends a streamed response: terminates the current line and leaves one blank line.
+    async fn finish_response(&mut self) -> Result<()> {
+        self.flush().await?;
+        self.emit("").await?;
+        self.emit("").await
+    }
+
+    /// Whether output reaches a terminal, where transient indicators can be drawn.
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl<T: OutputSink + ?Sized> OutputSink for Box<T> {
+    async fn emit(&mut self, text: &str) -> Result<()> {
+        (**self).emit(text).await
+    }
+
+    async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+        (**self).emit_chunk(chunk).await
+    }
+
+    async fn emit_error(&mut self, error: &str) -> Result<()> {
+        (**self).emit_error(error).await
+    }
+
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        (**self).emit_app_error(error).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        (**self).flush().await
+    }
+
+    async fn finish_response(&mut self) -> Result<()> {
+        (**self).finish_response().await
+    }
+
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        (**self).emit_reasoning(chunk).await
+    }
+
+    async fn begin_turn(&mut self, prompt: &str, model: &str) -> Result<()> {
+        (**self).begin_turn(prompt, model).await
+    }
+
+    fn record_stats(&mut self, stats: ResponseStats) {
+        (**self).record_stats(stats)
+    }
+
+    fn is_terminal(&self) -> bool {
+        (**self).is_terminal()
+    }
+}
+
+#[async_trait]
+impl<T: OutputSink + ?Sized> OutputSink for &mut T {
+    async fn emit(&mut self, text: &str) -> Result<()> {
+        (**self).emit(text).await
+    }
+
+    async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+        (**self).emit_chunk(chunk).await
+    }
+
+    async fn emit_error(&mut self, error: &str) -> Result<()> {
+        (**self).emit_error(error).await
+    }
+
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        (**self).emit_app_error(error).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        (**self).flush().await
+    }
+
+    async fn finish_response(&mut self) -> Result<()> {
+        (**self).finish_response().await
+    }
+
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        (**self).emit_reasoning(chunk).await
+    }
+
+    async fn begin_turn(&mut self, prompt: &str, model: &str) -> Result<()> {
+        (**self).begin_turn(prompt, model).await
+    }
+
+    fn record_stats(&mut self, stats: ResponseStats) {
+        (**self).record_stats(stats)
+    }
+
+    fn is_terminal(&self) -> bool {
+        (**self).is_terminal()
+    }
 }
 
 pub struct TextOutput {
     buffer: Vec<u8>,
     buffer_capacity: usize,
+    show_error_codes: bool,
 }
 
 impl TextOutput {
@@ -24,13 +155,21 @@ impl TextOutput {
         Self {
             buffer: Vec::with_capacity(Self::DEFAULT_BUFFER_SIZE),
             buffer_capacity: Self::DEFAULT_BUFFER_SIZE,
+            show_error_codes: false,
         }
     }
 
+    /// Starts application errors with their code, e.g. `[timeout]`, in its own color.
+    pub fn with_error_codes(mut self, show: bool) -> Self {
+        self.show_error_codes = show;
+        self
+    }
+
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             buffer: Vec::with_capacity(capacity),
             buffer_capacity: capacity,
+            show_error_codes: false,
         }
     }
 
@@ -77,9 +216,36 @@ impl OutputSink for TextOutput {
         Ok(())
     }
 
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        if !self.show_error_codes {
+            return self.emit_error(&error.to_string()).await;
+        }
+        self.flush_buffer().await?;
+
+        let line = format!(
+            "\x1b[31mError: \x1b[33m[{}]\x1b[31m {}\x1b[0m\n",
+            error.code(),
+            error
+        );
+        io::stderr().write_all(line.as_bytes()).await?;
+
+        Ok(())
+    }
+
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        if !self.is_terminal() {
+            return self.emit_chunk(chunk).await;
+        }
+        self.emit_chunk(&format!("\x1b[2m{}\x1b[0m", chunk)).await
+    }
+
     async fn flush(&mut self) -> Result<()> {
         self.flush_buffer().await
     }
+
+    fn is_terminal(&self) -> bool {
+        std::io::stdout().is_terminal()
+    }
 }
 
 impl Default for TextOutput {
@@ -90,11 +256,20 @@ impl Default for TextOutput {
 
 impl Drop for TextOutput {
     fn drop(&mut self) {
-        if !self.buffer.is_empty() {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        // Drop can't await, so fall back to a blocking write of whatever is left.
+        use std::io::Write;
+        let mut stdout = std::io::stdout();
+        if let Err(e) = stdout.write_all(&self.buffer).and_then(|_| stdout.flush()) {
             tracing::warn!(
-                "Output buffer not flushed, {} bytes lost",
-                self.buffer.len()
+                "Output buffer not flushed, {} bytes lost: {}",
+                self.buffer.len(),
+                e
             );
         }
+        self.buffer.clear();
     }
 }
@@ -0,0 +1,54 @@
+use std::io::Write;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+const FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const FRAME_INTERVAL: Duration = Duration::from_millis(80);
+
+/// An animated "thinking" line on stderr, erased when stopped or dropped.
+pub struct Spinner {
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl Spinner {
+    pub fn start() -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FRAME_INTERVAL);
+            for frame in FRAMES.iter().cycle() {
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    _ = interval.tick() => {
+                        eprint!("\r{} thinking...", frame);
+                        let _ = std::io::stderr().flush();
+                    }
+                }
+            }
+            eprint!("\r\x1b[2K");
+            let _ = std::io::stderr().flush();
+        });
+
+        Self {
+            stop: Some(stop),
+            task,
+        }
+    }
+
+    /// Stops the animation and waits until its line is erased.
+    pub async fn stop(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        // Dropping the sender also ends the task, which erases the line.
+        self.stop.take();
+    }
+}
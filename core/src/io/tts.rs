@@ -0,0 +1,169 @@
+use crate::config::VoiceConfig;
+use crate::error::{AppError, Result};
+use crate::io::{OutputSink, ResponseStats};
+use async_trait::async_trait;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+/// Mirrors output to an inner sink and speaks streamed responses sentence by sentence.
+pub struct VoiceOutput<S: OutputSink> {
+    inner: S,
+    pending: String,
+    sentences: mpsc::UnboundedSender<String>,
+}
+
+impl<S: OutputSink> VoiceOutput<S> {
+    pub fn new(inner: S, config: &VoiceConfig) -> Self {
+        tracing::info!("speaking responses with: {}", config.tts_command);
+
+        // Sentences queue up here so slow playback never blocks streaming.
+        let (sentences, rx) = mpsc::unbounded_channel();
+        tokio::spawn(speak_loop(config.tts_command.clone(), rx));
+
+        Self {
+            inner,
+            pending: String::new(),
+            sentences,
+        }
+    }
+
+    fn queue_complete_sentences(&mut self) {
+        while let Some(end) = sentence_end(&self.pending) {
+            let rest = self.pending.split_off(end);
+            let sentence = std::mem::replace(&mut self.pending, rest);
+            self.queue(sentence);
+        }
+    }
+
+    fn queue(&mut self, sentence: String) {
+        let sentence = sentence.trim();
+        if sentence.is_empty() {
+            return;
+        }
+
+        if self.sentences.send(sentence.to_string()).is_err() {
+            tracing::warn!("speech queue closed, dropping sentence");
+        }
+    }
+}
+
+async fn speak_loop(tts_command: String, mut rx: mpsc::UnboundedReceiver<String>) {
+    while let Some(sentence) = rx.recv().await {
+        if let Err(e) = speak(&tts_command, &sentence).await {
+            tracing::warn!("text to speech failed: {}", e);
+        }
+    }
+}
+
+async fn speak(tts_command: &str, sentence: &str) -> Result<()> {
+    tracing::debug!("speaking {} chars", sentence.len());
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(tts_command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| AppError::audio(format!("start tts command failed: {}", e)))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(sentence.as_bytes()).await?;
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(AppError::audio(format!(
+            "tts command exited with {}",
+            status
+        )));
+    }
+
+    Ok(())
+}
+
+/// Byte offset just past the first complete sentence in `text`. `.`, `!` and `?`
+/// only end one when whitespace follows, so "3.14" or "example.com" stays whole;
+/// punctuation at the very end waits for more text or `finish_response`.
+fn sentence_end(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' | '。' | '！' | '？' => return Some(i + c.len_utf8()),
+            '.' | '!' | '?' if chars.peek().is_some_and(|(_, next)| next.is_whitespace()) => {
+                return Some(i + c.len_utf8());
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[async_trait]
+impl<S: OutputSink> OutputSink for VoiceOutput<S> {
+    async fn emit(&mut self, text: &str) -> Result<()> {
+        self.inner.emit(text).await
+    }
+
+    async fn emit_chunk(&mut self, chunk: &str) -> Result<()> {
+        self.pending.push_str(chunk);
+        self.queue_complete_sentences();
+        self.inner.emit_chunk(chunk).await
+    }
+
+    async fn emit_error(&mut self, error: &str) -> Result<()> {
+        self.inner.emit_error(error).await
+    }
+
+    async fn emit_app_error(&mut self, error: &AppError) -> Result<()> {
+        self.inner.emit_app_error(error).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn finish_response(&mut self) -> Result<()> {
+        let rest = std::mem::take(&mut self.pending);
+        self.queue(rest);
+        self.inner.finish_response().await
+    }
+
+    /// Reasoning is shown but not spoken.
+    async fn emit_reasoning(&mut self, chunk: &str) -> Result<()> {
+        self.inner.emit_reasoning(chunk).await
+    }
+
+    async fn begin_turn(&mut self, prompt: &str, model: &str) -> Result<()> {
+        self.inner.begin_turn(prompt, model).await
+    }
+
+    fn record_stats(&mut self, stats: ResponseStats) {
+        self.inner.record_stats(stats)
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.inner.is_terminal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_at_punctuation_followed_by_whitespace() {
+        assert_eq!(sentence_end("Hi there. Next"), Some(9));
+        assert_eq!(sentence_end("Really?\nYes"), Some(7));
+        assert_eq!(sentence_end("line\nmore"), Some(5));
+        assert_eq!(sentence_end("好。再见"), Some("好。".len()));
+    }
+
+    #[test]
+    fn keeps_numbers_and_abbreviations_together() {
+        assert_eq!(sentence_end("Pi is 3.14 and"), None);
+        assert_eq!(sentence_end("see example.com or"), None);
+        assert_eq!(sentence_end("It ends here."), None);
+    }
+}
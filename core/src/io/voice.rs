@@ -1,15 +1,54 @@
-use crate::config::VoiceConfig;
+use crate::config::{VoiceConfig, VoiceMode};
 use crate::error::{AppError, Result};
 use crate::io::InputSource;
 use async_trait::async_trait;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, SampleRate, StreamConfig};
+use cpal::{BufferSize, Device, SampleRate, StreamConfig, SupportedBufferSize};
+use crossterm::event::{
+    Event, EventStream, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{execute, terminal};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, watch};
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
 
 const WHISPER_SAMPLE_RATE: u32 = 16000;
-const CHUNK_SIZE: usize = 1024;
+const START_CUE_HZ: f32 = 880.0;
+const STOP_CUE_HZ: f32 = 440.0;
+const CUE_DURATION: Duration = Duration::from_millis(120);
+// Lets the speaker ring out before the mic opens so the cue isn't captured.
+const CUE_CAPTURE_PAUSE: Duration = Duration::from_millis(80);
+// Chunks assumed to be silence at the start of an adaptive recording.
+const NOISE_CALIBRATION_CHUNKS: usize = 10;
+// Weight of each new silent chunk in the rolling noise floor once calibrated.
+const NOISE_FLOOR_SMOOTHING: f32 = 0.05;
+// Keeps a digitally silent input from making every chunk count as voice.
+const MIN_ADAPTIVE_THRESHOLD: f32 = 0.001;
+// Audio recorded between two interim transcriptions.
+const INTERIM_INTERVAL_SECS: f32 = 2.0;
+
+/// A transcript with the time span of each word.
+#[derive(Debug, Clone, Default)]
+pub struct Transcription {
+    pub text: String,
+    pub segments: Vec<Segment>,
+}
+
+/// One word, with offsets from the start of the audio.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum VoiceState {
@@ -18,12 +57,152 @@ enum VoiceState {
     SilenceDetected { silence_sample: usize },
 }
 
+/// Decides which chunks hold voice, optionally following the background noise floor.
+struct EnergyGate {
+    threshold: f32,
+    adaptive: bool,
+    multiplier: f32,
+    noise_floor: f32,
+    observed: usize,
+}
+
+impl EnergyGate {
+    fn new(config: &VoiceConfig) -> Self {
+        Self {
+            threshold: config.energy_threshold,
+            adaptive: config.adaptive_threshold,
+            multiplier: config.noise_floor_multiplier,
+            noise_floor: 0.0,
+            observed: 0,
+        }
+    }
+
+    fn calibrating(&self) -> bool {
+        self.adaptive && self.observed < NOISE_CALIBRATION_CHUNKS
+    }
+
+    fn has_voice(&self, energy: f32) -> bool {
+        !self.calibrating() && energy > self.threshold
+    }
+
+    /// Folds a chunk without voice into the noise floor and moves the threshold with it.
+    fn observe_silence(&mut self, energy: f32) {
+        if !self.adaptive {
+            return;
+        }
+
+        let was_calibrating = self.calibrating();
+        if was_calibrating {
+            self.observed += 1;
+            self.noise_floor += (energy - self.noise_floor) / self.observed as f32;
+        } else {
+            self.noise_floor += (energy - self.noise_floor) * NOISE_FLOOR_SMOOTHING;
+        }
+        self.threshold = (self.noise_floor * self.multiplier).clamp(MIN_ADAPTIVE_THRESHOLD, 1.0);
+
+        if was_calibrating && !self.calibrating() {
+            tracing::debug!(
+                "noise floor: {:.4}, energy threshold: {:.4}",
+                self.noise_floor,
+                self.threshold
+            );
+        }
+    }
+}
+
+/// Tracks the push-to-talk key during one recording and restores the terminal on drop.
+struct TalkKey {
+    held: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+    listener: tokio::task::JoinHandle<()>,
+    reports_release: bool,
+}
+
+impl TalkKey {
+    fn listen() -> Result<Self> {
+        // Without key release events a press toggles recording instead.
+        let reports_release = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_release {
+            eprintln!("🎤 Hold space to talk...");
+        } else {
+            eprintln!("🎤 Press space to start talking, and again to stop...");
+        }
+
+        terminal::enable_raw_mode()
+            .map_err(|e| AppError::audio(format!("enable raw mode failed: {}", e)))?;
+        if reports_release {
+            let _ = execute!(
+                std::io::stderr(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            );
+        }
+
+        let held = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let listener = tokio::spawn(Self::poll_keys(
+            held.clone(),
+            cancelled.clone(),
+            !reports_release,
+        ));
+
+        Ok(Self {
+            held,
+            cancelled,
+            listener,
+            reports_release,
+        })
+    }
+
+    async fn poll_keys(held: Arc<AtomicBool>, cancelled: Arc<AtomicBool>, toggle: bool) {
+        let mut events = EventStream::new();
+        while let Some(Ok(event)) = events.next().await {
+            let Event::Key(key) = event else {
+                continue;
+            };
+
+            match key.code {
+                // Raw mode swallows SIGINT, so Ctrl+C arrives as a key.
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    cancelled.store(true, Ordering::Relaxed);
+                    break;
+                }
+                KeyCode::Char(' ') => match key.kind {
+                    KeyEventKind::Press if toggle => {
+                        held.fetch_xor(true, Ordering::Relaxed);
+                    }
+                    KeyEventKind::Press | KeyEventKind::Repeat => {
+                        held.store(true, Ordering::Relaxed)
+                    }
+                    KeyEventKind::Release => held.store(false, Ordering::Relaxed),
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for TalkKey {
+    fn drop(&mut self) {
+        self.listener.abort();
+        if self.reports_release {
+            let _ = execute!(std::io::stderr(), PopKeyboardEnhancementFlags);
+        }
+        if let Err(e) = terminal::disable_raw_mode() {
+            tracing::warn!("disable raw mode failed: {}", e);
+        }
+    }
+}
+
 pub struct VoiceInput {
     whisper_ctx: WhisperContext,
     device: Device,
     config: VoiceConfig,
     device_sample_rate: u32,
+    /// Channels the device records natively; downmixed to mono while capturing.
+    device_channels: u16,
+    buffer_frames: Option<u32>,
     stop_signal: Arc<AtomicBool>,
+    interim_tx: Option<mpsc::UnboundedSender<String>>,
 }
 
 impl VoiceInput {
@@ -32,17 +211,29 @@ impl VoiceInput {
 
         let whisper_ctx = Self::init_whisper(&config.model_path)?;
 
-        let (device, device_sample_rate) = Self::init_audio_device()?;
+        let (device, device_sample_rate, device_channels, buffer_frames) =
+            Self::init_audio_device(config)?;
 
         Ok(Self {
             whisper_ctx,
             device,
             config: config.clone(),
             device_sample_rate,
+            device_channels,
+            buffer_frames,
             stop_signal: Arc::new(AtomicBool::new(false)),
+            interim_tx: None,
         })
     }
 
+    /// Receives partial transcripts of the recording in progress when
+    /// `interim_results` is enabled; the final transcript still comes from `next`.
+    pub fn interim_results(&mut self) -> mpsc::UnboundedReceiver<String> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.interim_tx = Some(tx);
+        rx
+    }
+
     fn init_whisper(model_path: &str) -> Result<WhisperContext> {
         tracing::debug!("loading Whisper model: {}", model_path);
 
@@ -62,9 +253,76 @@ impl VoiceInput {
         Ok(ctx)
     }
 
-    fn init_audio_device() -> Result<(Device, u32)> {
-        let host = cpal::default_host();
-        let device = host.default_input_device().ok_or(AppError::NoAudioDevice)?;
+    pub fn list_hosts() -> Vec<String> {
+        let default_id = cpal::default_host().id();
+        cpal::available_hosts()
+            .into_iter()
+            .map(|id| {
+                if id == default_id {
+                    format!("{} (default)", id.name())
+                } else {
+                    id.name().to_string()
+                }
+            })
+            .collect()
+    }
+
+    fn select_host(name: Option<&str>) -> cpal::Host {
+        let Some(name) = name else {
+            return cpal::default_host();
+        };
+
+        let host_id = cpal::available_hosts()
+            .into_iter()
+            .find(|id| id.name().eq_ignore_ascii_case(name));
+
+        match host_id.map(cpal::host_from_id) {
+            Some(Ok(host)) => host,
+            Some(Err(e)) => {
+                tracing::warn!("audio host {} unavailable: {}, using default", name, e);
+                cpal::default_host()
+            }
+            None => {
+                tracing::warn!("audio host {} not found, using default", name);
+                cpal::default_host()
+            }
+        }
+    }
+
+    /// Names of the input devices on `host`, or on the default audio host.
+    pub fn list_devices(host: Option<&str>) -> Result<Vec<String>> {
+        let devices = Self::select_host(host)
+            .input_devices()
+            .map_err(|e| AppError::audio(format!("get input devices failed: {}", e)))?;
+
+        Ok(devices.filter_map(|d| d.name().ok()).collect())
+    }
+
+    fn select_input_device(host: &cpal::Host, name: Option<&str>) -> Result<Device> {
+        if let Some(name) = name {
+            let needle = name.to_lowercase();
+            let found = host
+                .input_devices()
+                .map_err(|e| AppError::audio(format!("get input devices failed: {}", e)))?
+                .find(|d| {
+                    d.name()
+                        .map(|n| n.to_lowercase().contains(&needle))
+                        .unwrap_or(false)
+                });
+
+            match found {
+                Some(device) => return Ok(device),
+                None => tracing::warn!("audio device {} not found, using default", name),
+            }
+        }
+
+        host.default_input_device().ok_or(AppError::NoAudioDevice)
+    }
+
+    fn init_audio_device(config: &VoiceConfig) -> Result<(Device, u32, u16, Option<u32>)> {
+        let host = Self::select_host(config.audio_host.as_deref());
+        tracing::info!("using audio host: {}", host.id().name());
+        let device = Self::select_input_device(&host, config.device_name.as_deref())?;
         let device_name = device
             .name()
             .unwrap_or_else(|_| "unknown device".to_string());
@@ -75,25 +333,113 @@ impl VoiceInput {
             .map_err(|e| AppError::audio(format!("get audio config failed: {}", e)))?;
 
         let sample_rate = supported_config.sample_rate().0;
+        let channels = supported_config.channels().max(1);
         tracing::debug!(
-            "sample rate: {} Hz, format: {:?}",
+            "sample rate: {} Hz, channels: {}, format: {:?}",
             sample_rate,
+            channels,
             supported_config.sample_format()
         );
 
-        Ok((device, sample_rate))
+        let buffer_frames = Self::validate_buffer_frames(
+            config.audio_buffer_frames,
+            supported_config.buffer_size(),
+        )?;
+
+        Ok((device, sample_rate, channels, buffer_frames))
+    }
+
+    fn validate_buffer_frames(
+        requested: Option<u32>,
+        supported: &SupportedBufferSize,
+    ) -> Result<Option<u32>> {
+        let Some(frames) = requested else {
+            return Ok(None);
+        };
+
+        if let SupportedBufferSize::Range { min, max } = supported {
+            if frames < *min || frames > *max {
+                return Err(AppError::audio(format!(
+                    "audio_buffer_frames {} not supported by device (range {}..={})",
+                    frames, min, max
+                )));
+            }
+        }
+
+        tracing::debug!("using fixed audio buffer: {} frames", frames);
+        Ok(Some(frames))
+    }
+
+    /// Plays a short sine tone on the default output device, blocking until it ends.
+    fn play_cue(host: &cpal::Host, frequency: f32) {
+        if let Err(e) = Self::try_play_cue(host, frequency) {
+            tracing::warn!("play audio cue failed: {}", e);
+        }
+    }
+
+    fn try_play_cue(host: &cpal::Host, frequency: f32) -> Result<()> {
+        let device = host
+            .default_output_device()
+            .ok_or(AppError::NoAudioDevice)?;
+        let supported_config = device
+            .default_output_config()
+            .map_err(|e| AppError::audio(format!("get output config failed: {}", e)))?;
+        let stream_config: StreamConfig = supported_config.config();
+        let sample_rate = stream_config.sample_rate.0 as f32;
+        let channels = stream_config.channels as usize;
+
+        let mut phase = 0.0_f32;
+        let stream = device
+            .build_output_stream(
+                &stream_config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    for frame in data.chunks_mut(channels) {
+                        let sample = (phase * std::f32::consts::TAU).sin() * 0.2;
+                        phase = (phase + frequency / sample_rate) % 1.0;
+                        frame.fill(sample);
+                    }
+                },
+                |err| tracing::error!("audio cue stream error: {}", err),
+                None,
+            )
+            .map_err(|e| AppError::audio(format!("create cue stream failed: {}", e)))?;
+        stream
+            .play()
+            .map_err(|e| AppError::audio(format!("play cue failed: {}", e)))?;
+        std::thread::sleep(CUE_DURATION);
+        drop(stream);
+
+        Ok(())
     }
 
     async fn record_audio(&self) -> Result<Vec<f32>> {
         let stop_signal = self.stop_signal.clone();
         let device_sample_rate = self.device_sample_rate;
+        let device_channels = self.device_channels;
+        let buffer_frames = self.buffer_frames;
         let silence_threshold_secs = self.config.silience_threshold_secs;
         let max_duration_secs = self.config.max_duration_secs;
+        let min_recording_secs = self.config.min_recording_secs;
         // 在阻塞任务中获取设备并录音，因为 cpal::Stream 不是 Send
         let device_name = self.device.name().unwrap_or_else(|_| "unknown".to_string());
-        let audio_buffer = tokio::task::spawn_blocking(move || -> Result<Vec<f32>> {
+        let audio_host = self.config.audio_host.clone();
+        let audio_cues = self.config.audio_cues;
+        let mut energy_gate = EnergyGate::new(&self.config);
+        let talk_key = match self.config.mode {
+            VoiceMode::PushToTalk => Some(TalkKey::listen()?),
+            VoiceMode::Vad => None,
+        };
+        let push_to_talk = talk_key
+            .as_ref()
+            .map(|key| (key.held.clone(), key.cancelled.clone()));
+        let interim_interval = self
+            .config
+            .interim_results
+            .then_some((INTERIM_INTERVAL_SECS * device_sample_rate as f32) as usize);
+        let (snapshot_tx, mut snapshot_rx) = watch::channel(Vec::new());
+        let mut recording = tokio::task::spawn_blocking(move || -> Result<Vec<f32>> {
             // 在阻塞线程中重新获取设备
-            let host = cpal::default_host();
+            let host = Self::select_host(audio_host.as_deref());
             let device = host
                 .input_devices()
                 .map_err(|e| AppError::audio(format!("get input devices failed: {}", e)))?
@@ -101,10 +447,17 @@ impl VoiceInput {
                 .or_else(|| host.default_input_device())
                 .ok_or(AppError::NoAudioDevice)?;
             let stream_config = StreamConfig {
-                channels: 1,
+                channels: device_channels,
                 sample_rate: SampleRate(device_sample_rate),
-                buffer_size: cpal::BufferSize::Default,
+                buffer_size: match buffer_frames {
+                    Some(frames) => BufferSize::Fixed(frames),
+                    None => BufferSize::Default,
+                },
             };
+            if audio_cues {
+                Self::play_cue(&host, START_CUE_HZ);
+                std::thread::sleep(CUE_CAPTURE_PAUSE);
+            }
             // 使用标准库的 mpsc，因为在同步代码中
             let (tx, rx) = std::sync::mpsc::channel::<Vec<f32>>();
             let err_fn = |err| tracing::error!("audio stream error: {}", err);
@@ -112,7 +465,7 @@ impl VoiceInput {
                 .build_input_stream(
                     &stream_config,
                     move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                        let _ = tx.send(data.to_vec());
+                        let _ = tx.send(Self::downmix(data, device_channels));
                     },
                     err_fn,
                     None,
@@ -127,7 +480,9 @@ impl VoiceInput {
             let silence_threshold_sample =
                 (silence_threshold_secs * device_sample_rate as f32) as usize;
             let max_samples = (max_duration_secs * device_sample_rate as f32) as usize;
-            const ENERGY_THRESHOLD: f32 = 0.01;
+            let min_voiced_samples = (min_recording_secs * device_sample_rate as f32) as usize;
+            let mut voiced_samples = 0;
+            let mut interim_sent = 0;
             // 使用超时接收，这样可以检查 stop_signal
             let timeout = std::time::Duration::from_millis(100);
             loop {
@@ -135,13 +490,43 @@ impl VoiceInput {
                     tracing::debug!("recv the stop signal");
                     break;
                 }
+                if let Some(interval) = interim_interval {
+                    // The buffer shrinks when a too-short recording is discarded.
+                    interim_sent = interim_sent.min(audio_buffer.len());
+                    if audio_buffer.len() - interim_sent >= interval {
+                        interim_sent = audio_buffer.len();
+                        let _ = snapshot_tx.send(audio_buffer.clone());
+                    }
+                }
                 let chunk = match rx.recv_timeout(timeout) {
                     Ok(chunk) => chunk,
                     Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
                     Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
                 };
+                if let Some((held, cancelled)) = &push_to_talk {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return Err(AppError::Cancelled);
+                    }
+                    if held.load(Ordering::Relaxed) {
+                        audio_buffer.extend_from_slice(&chunk);
+                    } else if !audio_buffer.is_empty() {
+                        tracing::debug!("talk key released");
+                        break;
+                    }
+                    if audio_buffer.len() >= max_samples {
+                        tracing::warn!("reach max recording time");
+                        break;
+                    }
+                    continue;
+                }
                 let energy = Self::calculate_energy(&chunk);
-                let has_voice = energy > ENERGY_THRESHOLD;
+                let has_voice = energy_gate.has_voice(energy);
+                if state == VoiceState::WaitingForVoice && !has_voice {
+                    energy_gate.observe_silence(energy);
+                }
+                if has_voice {
+                    voiced_samples += chunk.len();
+                }
                 state = match state {
                     VoiceState::WaitingForVoice => {
                         if has_voice {
@@ -168,16 +553,25 @@ impl VoiceInput {
                             VoiceState::Recording
                         } else {
                             let new_silence = silence_sample + chunk.len();
-                            if new_silence >= silence_threshold_sample {
+                            if new_silence < silence_threshold_sample {
+                                VoiceState::SilenceDetected {
+                                    silence_sample: new_silence,
+                                }
+                            } else if voiced_samples < min_voiced_samples {
+                                tracing::debug!(
+                                    "discard short recording, voiced {} samples",
+                                    voiced_samples
+                                );
+                                audio_buffer.clear();
+                                voiced_samples = 0;
+                                VoiceState::WaitingForVoice
+                            } else {
                                 tracing::debug!(
                                     "detect voice stopped, silence {} samples",
                                     new_silence
                                 );
                                 break;
                             }
-                            VoiceState::SilenceDetected {
-                                silence_sample: new_silence,
-                            }
                         }
                     }
                 };
@@ -187,18 +581,64 @@ impl VoiceInput {
                 }
             }
             drop(stream);
+            if audio_cues {
+                Self::play_cue(&host, STOP_CUE_HZ);
+            }
             if audio_buffer.is_empty() {
                 return Err(AppError::audio("no audio signal"));
             }
             tracing::debug!("recording succeeded! sample points: {}", audio_buffer.len());
             Ok(audio_buffer)
-        })
-        .await
-        .map_err(|e| AppError::audio(format!("recording task failed: {}", e)))??;
+        });
+
+        // Whisper only ever sees the newest snapshot, so slow transcription never queues up.
+        let audio_buffer = loop {
+            tokio::select! {
+                result = &mut recording => {
+                    break result
+                        .map_err(|e| AppError::audio(format!("recording task failed: {}", e)))??;
+                }
+                Ok(()) = snapshot_rx.changed() => {
+                    let snapshot = snapshot_rx.borrow_and_update().clone();
+                    self.emit_interim(&snapshot);
+                }
+            }
+        };
+        drop(talk_key);
         let resampled = self.resample_audio(&audio_buffer)?;
         Ok(resampled)
     }
 
+    fn emit_interim(&self, audio: &[f32]) {
+        let text = match self
+            .resample_audio(audio)
+            .and_then(|resampled| self.transcribe(&resampled))
+        {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::warn!("interim transcription failed: {}", e);
+                return;
+            }
+        };
+
+        if let Some(ref tx) = self.interim_tx {
+            if !text.is_empty() {
+                let _ = tx.send(text);
+            }
+        }
+    }
+
+    /// Averages interleaved frames of `channels` samples into one mono sample each.
+    fn downmix(data: &[f32], channels: u16) -> Vec<f32> {
+        if channels <= 1 {
+            return data.to_vec();
+        }
+
+        data.chunks(channels as usize)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect()
+    }
+
     fn calculate_energy(samples: &[f32]) -> f32 {
         if samples.is_empty() {
             return 0.0;
@@ -209,21 +649,21 @@ impl VoiceInput {
     }
 
     fn resample_audio(&self, audio: &[f32]) -> Result<Vec<f32>> {
-        if self.device_sample_rate == WHISPER_SAMPLE_RATE {
+        Self::resample(audio, self.device_sample_rate)
+    }
+
+    fn resample(audio: &[f32], sample_rate: u32) -> Result<Vec<f32>> {
+        if sample_rate == WHISPER_SAMPLE_RATE {
             return Ok(audio.to_vec());
         }
 
         use rubato::{FftFixedInOut, Resampler};
 
-        let resample_ratio = WHISPER_SAMPLE_RATE as f64 / self.device_sample_rate as f64;
+        let resample_ratio = WHISPER_SAMPLE_RATE as f64 / sample_rate as f64;
 
-        let mut resampler = FftFixedInOut::<f32>::new(
-            self.device_sample_rate as usize,
-            WHISPER_SAMPLE_RATE as usize,
-            1024,
-            1,
-        )
-        .map_err(|e| AppError::audio(format!("create resampler failed: {}", e)))?;
+        let mut resampler =
+            FftFixedInOut::<f32>::new(sample_rate as usize, WHISPER_SAMPLE_RATE as usize, 1024, 1)
+                .map_err(|e| AppError::audio(format!("create resampler failed: {}", e)))?;
 
         let input_frames = resampler.input_frames_next();
         let mut output = Vec::new();
@@ -257,16 +697,82 @@ impl VoiceInput {
         Ok(output)
     }
 
-    fn transcribe(&self, audio: &[f32]) -> Result<String> {
-        tracing::debug!(
-            "start voice transcrining, audio len: {} points",
-            audio.len()
-        );
+    /// Writes 16 kHz mono audio to a timestamped WAV file in `dir`.
+    fn save_recording(dir: &Path, audio: &[f32]) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| AppError::audio(format!("create {} failed: {}", dir.display(), e)))?;
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let path = dir.join(format!("recording-{}.wav", millis));
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: WHISPER_SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let write = || -> std::result::Result<(), hound::Error> {
+            let mut writer = hound::WavWriter::create(&path, spec)?;
+            for sample in audio {
+                writer.write_sample(*sample)?;
+            }
+            writer.finalize()
+        };
+        write().map_err(|e| AppError::audio(format!("write {} failed: {}", path.display(), e)))?;
 
-        let mut state = self.whisper_ctx.create_state().map_err(|e| {
-            AppError::speech_recognition(format!("create Whisper status failed: {}", e))
-        })?;
+        Ok(path)
+    }
 
+    /// Transcribes a 16-bit integer or 32-bit float WAV file instead of the microphone.
+    pub fn transcribe_file(&self, path: &Path) -> Result<String> {
+        self.transcribe(&Self::load_wav(path)?)
+    }
+
+    /// Like `transcribe_file`, with word timestamps.
+    pub fn transcribe_file_detailed(&self, path: &Path) -> Result<Transcription> {
+        self.transcribe_detailed(&Self::load_wav(path)?)
+    }
+
+    /// Reads a WAV file as 16 kHz mono samples.
+    fn load_wav(path: &Path) -> Result<Vec<f32>> {
+        if !path.exists() {
+            return Err(AppError::audio(format!(
+                "audio file does not exist: {}",
+                path.display()
+            )));
+        }
+
+        let mut reader = hound::WavReader::open(path)
+            .map_err(|e| AppError::audio(format!("open wav {} failed: {}", path.display(), e)))?;
+        let spec = reader.spec();
+        tracing::debug!("wav spec: {:?}", spec);
+
+        let samples: Vec<f32> = match (spec.sample_format, spec.bits_per_sample) {
+            (hound::SampleFormat::Int, 16) => reader
+                .samples::<i16>()
+                .map(|s| s.map(|s| s as f32 / i16::MAX as f32))
+                .collect::<std::result::Result<_, _>>(),
+            (hound::SampleFormat::Float, 32) => reader
+                .samples::<f32>()
+                .collect::<std::result::Result<_, _>>(),
+            (format, bits) => {
+                return Err(AppError::audio(format!(
+                    "unsupported wav format: {} bit {:?}",
+                    bits, format
+                )))
+            }
+        }
+        .map_err(|e| AppError::audio(format!("read wav samples failed: {}", e)))?;
+
+        let mono = Self::downmix(&samples, spec.channels);
+
+        Self::resample(&mono, spec.sample_rate)
+    }
+
+    fn whisper_params(&self) -> FullParams<'_, '_> {
         let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
 
         if self.config.language != "auto" {
@@ -277,7 +783,19 @@ impl VoiceInput {
         params.set_print_progress(false);
         params.set_print_realtime(false);
         params.set_print_timestamps(false);
-        params.set_single_segment(true);
+        params
+    }
+
+    /// Runs Whisper over 16 kHz mono `audio`, returning the state holding its segments.
+    fn run_whisper(&self, audio: &[f32], params: FullParams) -> Result<(WhisperState, i32)> {
+        tracing::debug!(
+            "start voice transcrining, audio len: {} points",
+            audio.len()
+        );
+
+        let mut state = self.whisper_ctx.create_state().map_err(|e| {
+            AppError::speech_recognition(format!("create Whisper status failed: {}", e))
+        })?;
 
         state.full(params, audio).map_err(|e| {
             AppError::speech_recognition(format!("get transcribe result failed: {}", e))
@@ -287,6 +805,59 @@ impl VoiceInput {
             AppError::speech_recognition(format!("get segments count failed: {}", e))
         })?;
 
+        Ok((state, num_segments))
+    }
+
+    /// Transcribes 16 kHz mono `audio` into per-word segments with their offsets.
+    pub fn transcribe_detailed(&self, audio: &[f32]) -> Result<Transcription> {
+        let mut params = self.whisper_params();
+        params.set_token_timestamps(true);
+        // One word per segment, so segment offsets are word offsets.
+        params.set_split_on_word(true);
+        params.set_max_len(1);
+
+        let (state, num_segments) = self.run_whisper(audio, params)?;
+
+        let mut segments = Vec::new();
+        for i in 0..num_segments {
+            let Ok(text) = state.full_get_segment_text(i) else {
+                continue;
+            };
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            // Whisper reports offsets in units of 10 ms.
+            let start = state.full_get_segment_t0(i).unwrap_or_default();
+            let end = state.full_get_segment_t1(i).unwrap_or_default();
+            segments.push(Segment {
+                text: text.to_string(),
+                start_ms: start.max(0) as u64 * 10,
+                end_ms: end.max(0) as u64 * 10,
+            });
+        }
+
+        let joined = segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut text = joined.trim().to_string();
+        if self.config.normalize_transcript {
+            text = Self::normalize_transcript(&text);
+        }
+        tracing::info!("transcribe result: {} ({} segments)", text, segments.len());
+
+        Ok(Transcription { text, segments })
+    }
+
+    fn transcribe(&self, audio: &[f32]) -> Result<String> {
+        let mut params = self.whisper_params();
+        params.set_single_segment(true);
+
+        let (state, num_segments) = self.run_whisper(audio, params)?;
+
         let mut result = String::new();
         for i in 0..num_segments {
             if let Ok(segment) = state.full_get_segment_text(i) {
@@ -294,12 +865,30 @@ impl VoiceInput {
             }
         }
 
-        let trimmed = result.trim().to_string();
+        let mut trimmed = result.trim().to_string();
+        if self.config.normalize_transcript {
+            trimmed = Self::normalize_transcript(&trimmed);
+        }
         tracing::info!("transcribe result: {}", trimmed);
 
         Ok(trimmed)
     }
 
+    fn normalize_transcript(text: &str) -> String {
+        let mut chars = text.chars();
+        let Some(first) = chars.next() else {
+            return String::new();
+        };
+
+        let mut normalized: String = first.to_uppercase().chain(chars).collect();
+        // Only Latin text gets a period; other scripts have their own punctuation.
+        if normalized.ends_with(|c: char| c.is_ascii_alphanumeric()) {
+            normalized.push('.');
+        }
+
+        normalized
+    }
+
     pub fn stop(&self) {
         self.stop_signal.store(true, Ordering::Relaxed);
     }
@@ -308,27 +897,78 @@ impl VoiceInput {
 #[async_trait]
 impl InputSource for VoiceInput {
     async fn next(&mut self) -> Result<Option<String>> {
-        self.stop_signal.store(false, Ordering::Relaxed);
+        let max_empty_retries = self.config.max_empty_retries;
 
-        let audio = match self.record_audio().await {
-            Ok(audio) => audio,
-            Err(e) if matches!(e, AppError::Cancelled) => {
-                return Ok(None);
-            }
-            Err(e) => {
-                tracing::error!("recording failed: {}", e);
-                return Err(e);
+        for attempt in 0..=max_empty_retries {
+            self.stop_signal.store(false, Ordering::Relaxed);
+
+            let audio = match self.record_audio().await {
+                Ok(audio) => audio,
+                Err(AppError::Cancelled) => {
+                    return Ok(None);
+                }
+                Err(e) => {
+                    tracing::error!("recording failed: {}", e);
+                    return Err(e);
+                }
+            };
+
+            if let Some(ref dir) = self.config.save_recordings_dir {
+                match Self::save_recording(dir, &audio) {
+                    Ok(path) => tracing::info!("saved recording: {}", path.display()),
+                    Err(e) => tracing::warn!("save recording failed: {}", e),
+                }
             }
-        };
 
-        let text = self.transcribe(&audio)?;
+            let text = if self.config.timestamps {
+                let transcription = self.transcribe_detailed(&audio)?;
+                for segment in &transcription.segments {
+                    tracing::debug!(
+                        "[{} - {} ms] {}",
+                        segment.start_ms,
+                        segment.end_ms,
+                        segment.text
+                    );
+                }
+                transcription.text
+            } else {
+                self.transcribe(&audio)?
+            };
+
+            if let Some(command) = spoken_command(&self.config.commands, &text) {
+                tracing::info!("voice command: {} -> {}", text, command);
+                return Ok(Some(command.to_string()));
+            }
+            if !text.is_empty() {
+                return Ok(Some(text));
+            }
 
-        if text.is_empty() {
-            tracing::debug!("transcribe result is empty, waiting...");
-            return self.next().await;
+            tracing::debug!(
+                "transcribe result is empty ({}/{})",
+                attempt + 1,
+                max_empty_retries + 1
+            );
+            if attempt < max_empty_retries && self.config.prompt_on_empty {
+                eprintln!("\x07🎤 Didn't catch that, please say it again...");
+            }
         }
 
-        Ok(Some(text))
+        Err(AppError::speech_recognition(format!(
+            "no speech recognized after {} attempts",
+            max_empty_retries + 1
+        )))
+    }
+
+    fn set_translate(&mut self, translate: bool) -> bool {
+        tracing::debug!("translate set to {}", translate);
+        self.config.translate = translate;
+        true
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        tracing::debug!("closing voice input");
+        self.stop();
+        Ok(())
     }
 }
 
@@ -338,3 +978,42 @@ impl Drop for VoiceInput {
         tracing::debug!("voice input has been freed");
     }
 }
+
+/// The command configured for a whole utterance, matched without regard to
+/// case, punctuation or spacing since Whisper varies all three.
+fn spoken_command<'a>(commands: &'a HashMap<String, String>, text: &str) -> Option<&'a str> {
+    let spoken = spoken_words(text);
+    if spoken.is_empty() {
+        return None;
+    }
+    commands
+        .iter()
+        .find(|(phrase, _)| spoken_words(phrase) == spoken)
+        .map(|(_, command)| command.as_str())
+}
+
+fn spoken_words(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_utterances_match_spoken_commands() {
+        let commands = HashMap::from([
+            ("reset".to_string(), "/reset".to_string()),
+            ("start over".to_string(), "/reset".to_string()),
+        ]);
+
+        assert_eq!(spoken_command(&commands, "Reset."), Some("/reset"));
+        assert_eq!(spoken_command(&commands, " Start, over!"), Some("/reset"));
+        assert_eq!(spoken_command(&commands, "Reset the timer please."), None);
+        assert_eq!(spoken_command(&commands, "..."), None);
+    }
+}
@@ -1,25 +1,480 @@
 pub mod ollama;
+pub mod openai;
 
 use crate::error::Result;
 use async_trait::async_trait;
-use futures_util::Stream;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub use ollama::OllamaClient;
+pub use openai::OpenAiClient;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    /// Kept with the history only; requests carry role and content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<MessageMeta>,
+}
+
+impl Message {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            meta: None,
+        }
+    }
+
+    /// A copy with only what is sent to a provider.
+    pub fn without_meta(&self) -> Self {
+        Self::new(self.role, self.content.as_str())
+    }
+}
+
+/// When a history message was stored, and for replies which model wrote it
+/// and the token counts it reported.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageMeta {
+    pub timestamp_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u64>,
+}
+
+impl MessageMeta {
+    pub fn now() -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self {
+            timestamp_ms,
+            ..Self::default()
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct StreamChunk {
     pub text: String,
     pub done: bool,
+    /// Set on the final chunk when the backend reports token counts.
+    pub stats: Option<GenerationStats>,
+}
+
+/// Token counts and timing the backend reports for one response.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GenerationStats {
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    /// Time spent generating the completion tokens.
+    pub eval_duration: Option<Duration>,
+}
+
+impl GenerationStats {
+    pub fn tokens_per_sec(&self) -> Option<f64> {
+        let tokens = self.completion_tokens?;
+        let secs = self.eval_duration?.as_secs_f64();
+        (secs > 0.0).then(|| tokens as f64 / secs)
+    }
+}
+
+/// Width of the bar drawn by `PullProgress::render`.
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// One progress report while a model downloads. `status` is `"success"` once
+/// the model is installed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PullProgress {
+    pub status: String,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
+
+impl PullProgress {
+    pub fn is_success(&self) -> bool {
+        self.status == "success"
+    }
+
+    /// How much of the current layer is downloaded, when the size is known.
+    pub fn percent(&self) -> Option<u64> {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => Some(completed.min(total) * 100 / total),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => {
+                let ratio = completed.min(total) as f64 / total as f64;
+                let filled = (ratio * PROGRESS_BAR_WIDTH as f64) as usize;
+                format!(
+                    "{} [{}{}] {:>3.0}%",
+                    self.status,
+                    "#".repeat(filled),
+                    " ".repeat(PROGRESS_BAR_WIDTH - filled),
+                    ratio * 100.0
+                )
+            }
+            _ => self.status.clone(),
+        }
+    }
+}
+
+/// Operations a provider can serve, checked before calling it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProviderCapabilities {
+    pub embeddings: bool,
+    pub tools: bool,
+    pub images: bool,
+    pub streaming: bool,
+}
+
+/// An operation checked with `ProviderCapabilities::supports` before it is attempted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Embeddings,
+}
+
+impl Capability {
+    /// How the capability is named in errors.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Embeddings => "embeddings",
+        }
+    }
+}
+
+impl ProviderCapabilities {
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Embeddings => self.embeddings,
+        }
+    }
 }
 
 pub type ResponseStream = Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>;
+pub type PullStream = Pin<Box<dyn Stream<Item = Result<PullProgress>> + Send>>;
 
 #[async_trait]
 pub trait LlmProvider: Send + Sync {
     async fn health_check(&self) -> Result<()>;
 
-    async fn chat(&self, prompt: &str) -> Result<ResponseStream>;
+    /// Continues the conversation in `messages`, whose last entry is the new user turn.
+    async fn chat(&self, messages: &[Message]) -> Result<ResponseStream>;
+
+    /// Like `chat`, with `max_retries` in place of the configured attempt count.
+    async fn chat_with_retries(
+        &self,
+        messages: &[Message],
+        max_retries: u32,
+    ) -> Result<ResponseStream>;
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::default()
+    }
+
+    /// Models the backend can serve.
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Err(crate::error::AppError::Unsupported {
+            provider: self.name().to_string(),
+            capability: "listing models",
+        })
+    }
+
+    /// Returns the embedding vector for `text`.
+    async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+        Err(crate::error::AppError::Unsupported {
+            provider: self.name().to_string(),
+            capability: "embeddings",
+        })
+    }
+
+    /// Context window of the current model in tokens, when the backend reports it.
+    async fn context_window(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// `n` independent whole answers to `messages`, for picking one.
+    async fn alternatives(&self, _messages: &[Message], _n: u32) -> Result<Vec<String>> {
+        Err(crate::error::AppError::Unsupported {
+            provider: self.name().to_string(),
+            capability: "multiple completions",
+        })
+    }
+
+    /// Downloads the current model, streaming its progress until `success`.
+    async fn pull_model(&self) -> Result<PullStream> {
+        Err(crate::error::AppError::Unsupported {
+            provider: self.name().to_string(),
+            capability: "pulling models",
+        })
+    }
+
+    /// Settles the model once the backend is reachable: the first installed of
+    /// `preferences`, if any. Returns false when the model is known to be missing.
+    async fn resolve_model(&mut self, _preferences: &[String]) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Switches the model used for subsequent requests.
+    fn set_model(&mut self, _model: &str) -> Result<()> {
+        Err(crate::error::AppError::Unsupported {
+            provider: self.name().to_string(),
+            capability: "model switching",
+        })
+    }
 
     fn name(&self) -> &str;
+
+    /// The model requests are currently sent to.
+    fn model_name(&self) -> &str;
+
+    /// Identifies this provider instance in logs, e.g. which endpoint served a request.
+    fn describe(&self) -> String {
+        self.name().to_string()
+    }
+}
+
+/// Backoff before retry number `attempt` (starting at 1): 100 ms, doubling each time.
+pub fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_millis(100 * 2_u64.pow(attempt.saturating_sub(1)))
+}
+
+/// Merges chunks that arrive within `window` of the first one into a single chunk.
+pub fn coalesce(stream: ResponseStream, window: Duration) -> ResponseStream {
+    let state: (ResponseStream, Option<Result<StreamChunk>>) = (stream, None);
+
+    Box::pin(futures_util::stream::unfold(
+        state,
+        move |(mut stream, pending)| async move {
+            let first = match pending {
+                Some(item) => item,
+                None => stream.next().await?,
+            };
+
+            let mut chunk = match first {
+                Ok(chunk) => chunk,
+                Err(e) => return Some((Err(e), (stream, None))),
+            };
+
+            let deadline = tokio::time::Instant::now() + window;
+            let mut pending = None;
+
+            while !chunk.done {
+                match tokio::time::timeout_at(deadline, stream.next()).await {
+                    Ok(Some(Ok(next))) => {
+                        chunk.text.push_str(&next.text);
+                        chunk.done = next.done;
+                        chunk.stats = next.stats.or(chunk.stats);
+                    }
+                    Ok(Some(Err(e))) => {
+                        pending = Some(Err(e));
+                        break;
+                    }
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            Some((Ok(chunk), (stream, pending)))
+        },
+    ))
+}
+
+/// Shows `prefix`, which the reply was seeded with, ahead of the model's
+/// continuation. Models that start over by repeating the prefix have the repeat
+/// dropped; text that could still be a repeat is held back until it is decided.
+pub fn continue_prefix(stream: ResponseStream, prefix: String) -> ResponseStream {
+    let state = (stream, Some(String::new()));
+
+    Box::pin(futures_util::stream::unfold(
+        state,
+        move |(mut stream, held)| {
+            let prefix = prefix.clone();
+            async move {
+                let mut chunk = match stream.next().await? {
+                    Ok(chunk) => chunk,
+                    Err(e) => return Some((Err(e), (stream, held))),
+                };
+                let Some(mut held) = held else {
+                    return Some((Ok(chunk), (stream, None)));
+                };
+
+                held.push_str(&chunk.text);
+                let start = held.trim_start();
+                if !chunk.done && start.len() < prefix.len() && prefix.starts_with(start) {
+                    chunk.text = String::new();
+                    return Some((Ok(chunk), (stream, Some(held))));
+                }
+                chunk.text = join_prefix(&prefix, &held);
+                Some((Ok(chunk), (stream, None)))
+            }
+        },
+    ))
+}
+
+/// `prefix` followed by the model's `text`, without a repeat of the prefix.
+pub fn join_prefix(prefix: &str, text: &str) -> String {
+    let continuation = text.trim_start().strip_prefix(prefix).unwrap_or(text);
+    format!("{}{}", prefix, continuation)
+}
+
+/// Ends `stream` just before the first occurrence of any of `stops`, for
+/// backends that ignore the stop option. Text that could be the start of a stop
+/// sequence is held back until the next chunk decides it.
+pub fn stop_at(stream: ResponseStream, stops: Vec<String>) -> ResponseStream {
+    let state = (stream, String::new(), false);
+
+    Box::pin(futures_util::stream::unfold(
+        state,
+        move |(mut stream, mut pending, finished)| {
+            let stops = stops.clone();
+            async move {
+                if finished {
+                    return None;
+                }
+
+                let mut chunk = match stream.next().await {
+                    Some(Ok(chunk)) => chunk,
+                    Some(Err(e)) => return Some((Err(e), (stream, pending, true))),
+                    None if pending.is_empty() => return None,
+                    // Release held-back text; the caller sees the stream end as before.
+                    None => {
+                        let chunk = StreamChunk {
+                            text: std::mem::take(&mut pending),
+                            done: false,
+                            stats: None,
+                        };
+                        return Some((Ok(chunk), (stream, pending, true)));
+                    }
+                };
+                pending.push_str(&chunk.text);
+
+                let first_stop = stops
+                    .iter()
+                    .filter_map(|stop| pending.find(stop.as_str()))
+                    .min();
+                if let Some(index) = first_stop {
+                    tracing::debug!("Stop sequence reached, ending stream");
+                    pending.truncate(index);
+                    chunk.text = std::mem::take(&mut pending);
+                    chunk.done = true;
+                    return Some((Ok(chunk), (stream, pending, true)));
+                }
+
+                let held = if chunk.done {
+                    0
+                } else {
+                    partial_stop_len(&pending, &stops)
+                };
+                let rest = pending.split_off(pending.len() - held);
+                chunk.text = std::mem::replace(&mut pending, rest);
+                let done = chunk.done;
+                Some((Ok(chunk), (stream, pending, done)))
+            }
+        },
+    ))
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of a stop sequence.
+fn partial_stop_len(text: &str, stops: &[String]) -> usize {
+    stops
+        .iter()
+        .flat_map(|stop| {
+            (1..stop.len())
+                .rev()
+                .filter(|&len| stop.is_char_boundary(len))
+                .find(|&len| text.ends_with(&stop[..len]))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Consumes `stream` eagerly in the background but yields at most `chunks_per_sec` chunks.
+pub fn pace(mut stream: ResponseStream, chunks_per_sec: u32) -> ResponseStream {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Some(item) = stream.next().await {
+            if tx.send(item).is_err() {
+                break;
+            }
+        }
+    });
+
+    let interval = Duration::from_secs_f64(1.0 / chunks_per_sec as f64);
+    let state: (_, Option<tokio::time::Instant>) = (rx, None);
+
+    Box::pin(futures_util::stream::unfold(
+        state,
+        move |(mut rx, last_emit)| async move {
+            if let Some(last_emit) = last_emit {
+                tokio::time::sleep_until(last_emit + interval).await;
+            }
+
+            let item = rx.recv().await?;
+            Some((item, (rx, Some(tokio::time::Instant::now()))))
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(texts: &[&str]) -> ResponseStream {
+        let last = texts.len() - 1;
+        let items: Vec<Result<StreamChunk>> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                Ok(StreamChunk {
+                    text: text.to_string(),
+                    done: i == last,
+                    stats: None,
+                })
+            })
+            .collect();
+        Box::pin(futures_util::stream::iter(items))
+    }
+
+    async fn continued(texts: &[&str]) -> String {
+        let stream = continue_prefix(chunks(texts), "Sure,".to_string());
+        stream
+            .map(|item| item.unwrap().text)
+            .collect::<Vec<_>>()
+            .await
+            .concat()
+    }
+
+    #[tokio::test]
+    async fn prefix_is_shown_before_the_continuation() {
+        assert_eq!(continued(&[" here", " it is."]).await, "Sure, here it is.");
+    }
+
+    #[tokio::test]
+    async fn repeated_prefix_is_dropped() {
+        assert_eq!(
+            continued(&["Su", "re, here", " it is."]).await,
+            "Sure, here it is."
+        );
+        assert_eq!(continued(&["Su"]).await, "Sure,Su");
+    }
 }
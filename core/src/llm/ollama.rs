@@ -1,16 +1,50 @@
+use crate::config::{ApiMode, HealthMethod, OllamaConfig, SamplingConfig, Truncation};
 use crate::error::{AppError, Result};
-use crate::llm::{LlmProvider, ResponseStream, StreamChunk};
+use crate::llm::{
+    GenerationStats, LlmProvider, Message, ProviderCapabilities, PullProgress, PullStream,
+    ResponseStream, Role, StreamChunk,
+};
 use async_trait::async_trait;
-use futures_util::StreamExt;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const UNIX_SOCKET_SCHEME: &str = "unix://";
+// Model downloads can take far longer than a chat request.
+const PULL_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+const MAX_LOGGED_BODY_BYTES: usize = 4096;
 
 #[derive(Debug, Serialize)]
 struct GenerateRequest {
     model: String,
     prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    stream: bool,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    options: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: String,
+    messages: &'a [Message],
     stream: bool,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    options: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ApiRequest<'a> {
+    Generate(GenerateRequest),
+    Chat(ChatRequest<'a>),
 }
 
 #[derive(Debug, Deserialize)]
@@ -21,62 +55,535 @@ struct GenerateResponse {
     done: bool,
     #[serde(default)]
     error: Option<String>,
+    #[serde(flatten)]
+    counts: EvalCounts,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    #[serde(default)]
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(flatten)]
+    counts: EvalCounts,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    #[serde(default)]
+    content: String,
+}
+
+/// Token counts Ollama adds to the final frame of a response.
+#[derive(Debug, Default, Deserialize)]
+struct EvalCounts {
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    /// Nanoseconds spent generating `eval_count` tokens.
+    #[serde(default)]
+    eval_duration: Option<u64>,
+}
+
+impl EvalCounts {
+    fn into_stats(self) -> Option<GenerationStats> {
+        if self.prompt_eval_count.is_none() && self.eval_count.is_none() {
+            return None;
+        }
+
+        Some(GenerationStats {
+            prompt_tokens: self.prompt_eval_count,
+            completion_tokens: self.eval_count,
+            eval_duration: self.eval_duration.map(Duration::from_nanos),
+        })
+    }
+}
+
+/// One response frame, whichever endpoint produced it.
+struct ResponseFrame {
+    text: String,
+    done: bool,
+    error: Option<String>,
+    stats: Option<GenerationStats>,
+}
+
+impl ResponseFrame {
+    fn parse(api_mode: ApiMode, bytes: &[u8]) -> serde_json::Result<Self> {
+        Ok(match api_mode {
+            ApiMode::Generate => {
+                let response: GenerateResponse = serde_json::from_slice(bytes)?;
+                Self {
+                    text: response.response,
+                    done: response.done,
+                    error: response.error,
+                    stats: response.counts.into_stats(),
+                }
+            }
+            ApiMode::Chat => {
+                let response: ChatResponse = serde_json::from_slice(bytes)?;
+                Self {
+                    text: response.message.map(|m| m.content).unwrap_or_default(),
+                    done: response.done,
+                    error: response.error,
+                    stats: response.counts.into_stats(),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    #[serde(default)]
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct PullRequest<'a> {
+    model: &'a str,
+    stream: bool,
+}
+
+/// A line of the /api/pull stream.
+#[derive(Debug, Deserialize)]
+struct PullFrame {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    completed: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl PullFrame {
+    fn into_progress(self) -> Result<PullProgress> {
+        match self.error {
+            Some(error) => Err(AppError::Llm(error)),
+            None => Ok(PullProgress {
+                status: self.status,
+                total: self.total,
+                completed: self.completed,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ShowRequest<'a> {
+    model: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShowResponse {
+    /// Modelfile parameters, one "name value" pair per line.
+    #[serde(default)]
+    parameters: String,
+    #[serde(default)]
+    model_info: HashMap<String, serde_json::Value>,
+}
+
+impl ShowResponse {
+    /// `num_ctx` from the Modelfile, else the context length the model was trained with.
+    fn context_window(&self) -> Option<u64> {
+        let num_ctx = self.parameters.lines().find_map(|line| {
+            let (name, value) = line.split_once(char::is_whitespace)?;
+            if name != "num_ctx" {
+                return None;
+            }
+            value.trim().parse().ok()
+        });
+        num_ctx.or_else(|| {
+            self.model_info
+                .iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .and_then(|(_, value)| value.as_u64())
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<ModelTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelTag {
+    name: String,
 }
 
 pub struct OllamaClient {
     client: Client,
     base_url: String,
     model_name: String,
+    embedding_model: Option<String>,
     max_retries: u32,
+    stream_idle_timeout: Duration,
+    api_mode: ApiMode,
+    stream: bool,
+    retry_budget: Option<u32>,
+    retry_budget_window: Duration,
+    /// When each retry within the budget window happened, oldest first; cleared
+    /// by a successful request.
+    recent_retries: Mutex<VecDeque<Instant>>,
+    sampling: SamplingConfig,
+    extra_options: HashMap<String, serde_json::Value>,
+    stop: Vec<String>,
+    health_path: String,
+    health_method: HealthMethod,
+    health_cache_ttl: Duration,
+    /// When the last health check succeeded; cleared on any request failure,
+    /// including errors part-way through a stream.
+    last_healthy: Arc<Mutex<Option<Instant>>>,
+    log_request_bodies: bool,
+    log_truncation: Truncation,
 }
 
 impl OllamaClient {
-    pub fn new(
-        base_url: &str,
-        model_name: &str,
-        timeout_secs: u64,
-        max_retries: u32,
-    ) -> Result<Self> {
-        Self::validate_config(base_url, model_name)?;
+    pub fn new(cfg: &OllamaConfig) -> Result<Self> {
+        Self::validate_config(cfg)?;
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(timeout_secs))
-            .build()?;
+        let mut builder = Client::builder().timeout(Duration::from_secs(cfg.timeout_secs));
+
+        if let Some(ref ca_path) = cfg.tls_ca_cert {
+            tracing::info!("Loading custom root CA: {}", ca_path);
+            let pem = std::fs::read(ca_path).map_err(|e| {
+                AppError::config(format!("read tls_ca_cert {} failed: {}", ca_path, e))
+            })?;
+            let cert = reqwest::Certificate::from_pem(&pem)
+                .map_err(|e| AppError::config(format!("invalid tls_ca_cert {}: {}", ca_path, e)))?;
+            builder = builder.add_root_certificate(cert);
+        }
+
+        if cfg.danger_accept_invalid_certs {
+            tracing::warn!(
+                "!!! TLS certificate validation is DISABLED for {} - connections are NOT secure !!!",
+                cfg.base_url
+            );
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        let base_url = match cfg.base_url.strip_prefix(UNIX_SOCKET_SCHEME) {
+            Some(socket_path) => {
+                builder = Self::with_unix_socket(builder, socket_path)?;
+                // The host is ignored when connecting over a unix socket.
+                "http://localhost".to_string()
+            }
+            None => cfg.base_url.trim_end_matches('/').to_string(),
+        };
+
+        let client = builder.build()?;
 
         Ok(Self {
             client,
-            base_url: base_url.trim_end_matches('/').to_string(),
-            model_name: model_name.to_string(),
-            max_retries,
+            base_url,
+            model_name: cfg.model_name.clone(),
+            embedding_model: cfg.embedding_model.clone(),
+            max_retries: cfg.max_retries,
+            stream_idle_timeout: Duration::from_secs(cfg.stream_idle_timeout_secs),
+            api_mode: cfg.api_mode,
+            stream: cfg.stream,
+            retry_budget: cfg.retry_budget,
+            retry_budget_window: Duration::from_secs(cfg.retry_budget_window_secs),
+            recent_retries: Mutex::new(VecDeque::new()),
+            sampling: cfg.sampling,
+            extra_options: cfg.extra_options.clone(),
+            stop: cfg.stop.clone(),
+            health_path: cfg.health_path.clone(),
+            health_method: cfg.health_method,
+            health_cache_ttl: Duration::from_secs(cfg.health_cache_ttl_secs),
+            last_healthy: Arc::new(Mutex::new(None)),
+            log_request_bodies: cfg.log_request_bodies,
+            log_truncation: Truncation::default(),
         })
     }
 
-    fn validate_config(base_url: &str, model_name: &str) -> Result<()> {
-        if base_url.is_empty() {
+    #[cfg(unix)]
+    fn with_unix_socket(
+        builder: reqwest::ClientBuilder,
+        socket_path: &str,
+    ) -> Result<reqwest::ClientBuilder> {
+        if !std::path::Path::new(socket_path).exists() {
+            return Err(AppError::config(format!(
+                "unix socket does not exist: {}",
+                socket_path
+            )));
+        }
+
+        tracing::info!("Connecting to Ollama over unix socket: {}", socket_path);
+        Ok(builder.unix_socket(std::path::PathBuf::from(socket_path)))
+    }
+
+    #[cfg(not(unix))]
+    fn with_unix_socket(
+        _builder: reqwest::ClientBuilder,
+        socket_path: &str,
+    ) -> Result<reqwest::ClientBuilder> {
+        Err(AppError::config(format!(
+            "unix sockets are not supported on this platform: {}",
+            socket_path
+        )))
+    }
+
+    fn validate_config(cfg: &OllamaConfig) -> Result<()> {
+        if cfg.base_url.is_empty() {
             return Err(AppError::invalid_input("base url can not be empty"));
         }
-        if model_name.is_empty() {
+        if cfg.model_name.is_empty() && cfg.model_preferences.is_empty() {
             return Err(AppError::invalid_input("model_name can not be empty"));
         }
         Ok(())
     }
 
-    pub async fn chat_stream_with_retry(&self, prompt: &str) -> Result<ResponseStream> {
+    pub fn with_log_truncation(mut self, truncation: Truncation) -> Self {
+        self.log_truncation = truncation;
+        self
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let tags = async {
+            let tags: TagsResponse = self
+                .client
+                .get(format!("{}/api/tags", self.base_url))
+                .send()
+                .await
+                .map_err(|e| AppError::service_unvailable(format!("connect failed: {}", e)))?
+                .error_for_status()
+                .map_err(|e| AppError::llm(format!("list models failed: {}", e)))?
+                .json()
+                .await?;
+            Ok::<_, AppError>(tags)
+        };
+        let tags = self.track_health(tags.await)?;
+
+        Ok(tags.models.into_iter().map(|m| m.name).collect())
+    }
+
+    pub async fn select_preferred_model(&mut self, preferences: &[String]) -> Result<()> {
+        let installed = self.list_models().await?;
+        tracing::debug!("Installed models: {:?}", installed);
+
+        let selected = preferences.iter().find_map(|preferred| {
+            installed
+                .iter()
+                .find(|name| Self::model_matches(name, preferred))
+        });
+
+        match selected {
+            Some(name) => {
+                tracing::info!("Selected installed model from preferences: {}", name);
+                self.model_name = name.clone();
+                Ok(())
+            }
+            None => Err(AppError::config(format!(
+                "none of the preferred models are installed (preferred: [{}], installed: [{}])",
+                preferences.join(", "),
+                installed.join(", ")
+            ))),
+        }
+    }
+
+    fn model_matches(installed: &str, preferred: &str) -> bool {
+        installed == preferred || installed == format!("{}:latest", preferred)
+    }
+
+    /// Warns when the configured model is not installed, naming the closest one that is.
+    /// Returns false only when the model is known to be missing.
+    pub async fn validate_model(&self) -> bool {
+        let installed = match self.list_models().await {
+            Ok(installed) => installed,
+            Err(e) => {
+                tracing::warn!(
+                    "Could not list models to check that {} is installed: {}",
+                    self.model_name,
+                    e
+                );
+                return true;
+            }
+        };
+
+        if installed
+            .iter()
+            .any(|name| Self::model_matches(name, &self.model_name))
+        {
+            return true;
+        }
+
+        let suggestion = installed
+            .iter()
+            .min_by_key(|name| edit_distance(name, &self.model_name))
+            .map(|name| format!(" Did you mean {}?", name))
+            .unwrap_or_default();
+        tracing::warn!(
+            "!!! Model {} is not installed in Ollama (installed: [{}]).{} Run `ollama pull {}` to install it.",
+            self.model_name,
+            installed.join(", "),
+            suggestion,
+            self.model_name
+        );
+        false
+    }
+
+    /// The context window requests to the current model get; a `num_ctx`
+    /// in `extra_options` wins over what the model reports.
+    pub async fn context_window(&self) -> Result<Option<u64>> {
+        if let Some(num_ctx) = self.extra_options.get("num_ctx").and_then(|v| v.as_u64()) {
+            return Ok(Some(num_ctx));
+        }
+
+        let show = async {
+            let show: ShowResponse = self
+                .client
+                .post(format!("{}/api/show", self.base_url))
+                .json(&ShowRequest {
+                    model: &self.model_name,
+                })
+                .send()
+                .await
+                .map_err(|e| AppError::service_unvailable(format!("connect failed: {}", e)))?
+                .error_for_status()
+                .map_err(|e| AppError::llm(format!("show model failed: {}", e)))?
+                .json()
+                .await?;
+            Ok::<_, AppError>(show)
+        };
+        let show = self.track_health(show.await)?;
+
+        let window = show.context_window();
+        tracing::debug!("Context window of {}: {:?}", self.model_name, window);
+        Ok(window)
+    }
+
+    /// Embeds `text` with the embedding model, falling back to the chat model.
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let model = self.embedding_model.as_deref().unwrap_or(&self.model_name);
+        tracing::debug!("Embedding {} chars with {}", text.len(), model);
+
+        let response = async {
+            let response: EmbeddingResponse = self
+                .client
+                .post(format!("{}/api/embeddings", self.base_url))
+                .json(&EmbeddingRequest {
+                    model,
+                    prompt: text,
+                })
+                .send()
+                .await
+                .map_err(|e| AppError::service_unvailable(format!("connect failed: {}", e)))?
+                .error_for_status()
+                .map_err(|e| AppError::llm(format!("embedding failed: {}", e)))?
+                .json()
+                .await?;
+            Ok::<_, AppError>(response)
+        };
+        let response = self.track_health(response.await)?;
+
+        if response.embedding.is_empty() {
+            return Err(AppError::llm(format!(
+                "{} returned an empty embedding; is it an embedding model?",
+                model
+            )));
+        }
+
+        Ok(response.embedding)
+    }
+
+    /// Downloads the configured model, yielding one progress line per frame.
+    pub async fn pull_model(&self) -> Result<PullStream> {
+        tracing::info!("Pulling model: {}", self.model_name);
+
+        let resp = self
+            .client
+            .post(format!("{}/api/pull", self.base_url))
+            .timeout(PULL_TIMEOUT)
+            .json(&PullRequest {
+                model: &self.model_name,
+                stream: true,
+            })
+            .send()
+            .await
+            .map_err(|e| AppError::service_unvailable(format!("connect failed: {}", e)))
+            .and_then(|resp| {
+                resp.error_for_status()
+                    .map_err(|e| AppError::llm(format!("pull failed: {}", e)))
+            });
+        let resp = self.track_health(resp)?;
+
+        let bytes: ByteStream = Box::pin(resp.bytes_stream());
+        let state = (bytes, Vec::<u8>::new(), false);
+        let stream =
+            futures_util::stream::unfold(state, |(mut bytes, mut buffer, finished)| async move {
+                if finished {
+                    return None;
+                }
+
+                loop {
+                    if let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=pos).collect();
+                        let line = line.trim_ascii();
+                        if line.is_empty() {
+                            continue;
+                        }
+
+                        let item = serde_json::from_slice::<PullFrame>(line)
+                            .map_err(AppError::Json)
+                            .and_then(PullFrame::into_progress);
+                        let finished = !matches!(&item, Ok(progress) if !progress.is_success());
+                        return Some((item, (bytes, buffer, finished)));
+                    }
+
+                    match bytes.next().await {
+                        Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                        Some(Err(e)) => {
+                            return Some((Err(AppError::Http(e)), (bytes, buffer, true)))
+                        }
+                        None => return Some((Err(AppError::StreamEnded), (bytes, buffer, true))),
+                    }
+                }
+            });
+
+        Ok(self.track_stream_health(Box::pin(stream)))
+    }
+
+    pub async fn chat_stream_with_retry(
+        &self,
+        messages: &[Message],
+        max_retries: u32,
+    ) -> Result<ResponseStream> {
         let mut last_error = None;
 
-        for attempt in 1..=self.max_retries {
-            match self.send_chat_request(prompt).await {
+        for attempt in 1..=max_retries {
+            let result = self.send_chat_request(messages).await;
+            if result.is_err() {
+                self.set_healthy(false);
+            }
+
+            match result {
                 Ok(stream) => {
                     if attempt > 1 {
                         tracing::info!("Request succeeded on attempt {}", attempt);
                     }
+                    self.refill_retry_budget();
                     return Ok(stream);
                 }
-                Err(e) if e.is_retryable() && attempt < self.max_retries => {
-                    tracing::warn!("Attempt {}/{} failed: {}", attempt, self.max_retries, e);
+                Err(e) if e.is_retryable() && attempt < max_retries => {
+                    tracing::warn!("Attempt {}/{} failed: {}", attempt, max_retries, e);
+                    self.take_retry_from_budget(&e)?;
                     last_error = Some(e);
 
-                    let delay = Duration::from_millis(100 * 2_u64.pow(attempt - 1));
+                    let delay = super::retry_delay(attempt);
                     tracing::debug!("Retrying after {:?}", delay);
                     tokio::time::sleep(delay).await;
                 }
@@ -87,19 +594,222 @@ impl OllamaClient {
         }
 
         Err(last_error.unwrap_or(AppError::RetryExhausted {
-            attempts: self.max_retries,
+            attempts: max_retries,
+        }))
+    }
+
+    /// Records one retry against the session-wide budget, failing once it is spent.
+    fn take_retry_from_budget(&self, last_error: &AppError) -> Result<()> {
+        let Some(budget) = self.retry_budget else {
+            return Ok(());
+        };
+
+        let mut recent = self
+            .recent_retries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        while recent
+            .front()
+            .is_some_and(|at| at.elapsed() >= self.retry_budget_window)
+        {
+            recent.pop_front();
+        }
+
+        if recent.len() >= budget as usize {
+            tracing::warn!("Retry budget of {} exhausted, not retrying", budget);
+            return Err(AppError::RetryBudgetExhausted {
+                retries: budget,
+                window_secs: self.retry_budget_window.as_secs(),
+                last_error: last_error.to_string(),
+            });
+        }
+
+        recent.push_back(Instant::now());
+        Ok(())
+    }
+
+    /// A successful request shows the backend has recovered, so the budget starts over.
+    fn refill_retry_budget(&self) {
+        let mut recent = self
+            .recent_retries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if !recent.is_empty() {
+            tracing::debug!("Request succeeded, refilling the retry budget");
+            recent.clear();
+        }
+    }
+
+    fn health_cached(&self) -> bool {
+        let last_healthy = self.last_healthy.lock().unwrap_or_else(|e| e.into_inner());
+        last_healthy.is_some_and(|at| at.elapsed() < self.health_cache_ttl)
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        let mut last_healthy = self.last_healthy.lock().unwrap_or_else(|e| e.into_inner());
+        *last_healthy = healthy.then(Instant::now);
+    }
+
+    fn track_health<T>(&self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.set_healthy(false);
+        }
+        result
+    }
+
+    /// Forgets the cached health status when `stream` fails part-way.
+    fn track_stream_health<T: Send + 'static>(
+        &self,
+        stream: Pin<Box<dyn Stream<Item = Result<T>> + Send>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>> {
+        let last_healthy = self.last_healthy.clone();
+        Box::pin(stream.inspect(move |item| {
+            if item.is_err() {
+                *last_healthy.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            }
         }))
     }
 
-    async fn send_chat_request(&self, prompt: &str) -> Result<ResponseStream> {
-        tracing::debug!("Sending prompt to Ollama (length: {})", prompt.len());
+    /// Leading system messages go into the /api/generate `system` field.
+    fn system_prompt(messages: &[Message]) -> Option<String> {
+        let system: Vec<&str> = messages
+            .iter()
+            .take_while(|m| m.role == Role::System)
+            .map(|m| m.content.as_str())
+            .collect();
+
+        (!system.is_empty()).then(|| system.join("\n\n"))
+    }
+
+    fn conversation(messages: &[Message]) -> &[Message] {
+        let start = messages
+            .iter()
+            .position(|m| m.role != Role::System)
+            .unwrap_or(messages.len());
+        &messages[start..]
+    }
+
+    /// Flattens the conversation into a single /api/generate prompt. A lone user
+    /// message is sent verbatim.
+    fn render_prompt(messages: &[Message]) -> String {
+        // A trailing assistant message is the start of the reply, to be continued.
+        let (messages, reply_start) = match messages {
+            [rest @ .., last] if last.role == Role::Assistant => (rest, Some(&last.content)),
+            _ => (messages, None),
+        };
+        if let ([only], None) = (messages, reply_start) {
+            return only.content.clone();
+        }
+
+        let mut prompt = String::new();
+        for message in messages {
+            let speaker = match message.role {
+                Role::System => "System",
+                Role::User => "User",
+                Role::Assistant => "Assistant",
+            };
+            prompt.push_str(&format!("{}: {}\n\n", speaker, message.content));
+        }
+        prompt.push_str("Assistant:");
+        if let Some(start) = reply_start {
+            prompt.push(' ');
+            prompt.push_str(start);
+        }
+
+        prompt
+    }
+
+    fn request_options(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut options = match serde_json::to_value(self.sampling) {
+            Ok(serde_json::Value::Object(sampling)) => sampling,
+            _ => serde_json::Map::new(),
+        };
+
+        if !self.stop.is_empty() {
+            options.insert("stop".to_string(), serde_json::json!(self.stop));
+        }
+
+        for (key, value) in &self.extra_options {
+            options.insert(key.clone(), value.clone());
+        }
+
+        options
+    }
+
+    fn build_request<'a>(
+        &self,
+        messages: &'a [Message],
+        stream: bool,
+        options: serde_json::Map<String, serde_json::Value>,
+    ) -> (String, ApiRequest<'a>) {
+        match self.api_mode {
+            ApiMode::Generate => (
+                format!("{}/api/generate", self.base_url),
+                ApiRequest::Generate(GenerateRequest {
+                    model: self.model_name.clone(),
+                    prompt: Self::render_prompt(Self::conversation(messages)),
+                    system: Self::system_prompt(messages),
+                    stream,
+                    options,
+                }),
+            ),
+            ApiMode::Chat => (
+                format!("{}/api/chat", self.base_url),
+                ApiRequest::Chat(ChatRequest {
+                    model: self.model_name.clone(),
+                    messages,
+                    stream,
+                    options,
+                }),
+            ),
+        }
+    }
+
+    /// One whole, unstreamed answer sampled with `seed`.
+    async fn complete_with_seed(&self, messages: &[Message], seed: u64) -> Result<String> {
+        let mut options = self.request_options();
+        options.insert("seed".to_string(), serde_json::json!(seed));
+        let (url, request) = self.build_request(messages, false, options);
 
-        let url = format!("{}/api/generate", self.base_url);
-        let request = GenerateRequest {
-            model: self.model_name.clone(),
-            prompt: prompt.to_string(),
-            stream: true,
+        let body = async {
+            let body = self
+                .client
+                .post(&url)
+                .json(&request)
+                .send()
+                .await?
+                .error_for_status()
+                .map_err(|e| AppError::llm(format!("API error: {}", e)))?
+                .bytes()
+                .await?;
+            Ok::<_, AppError>(body)
         };
+        let body = self.track_health(body.await)?;
+
+        let response = ResponseFrame::parse(self.api_mode, &body)?;
+        match response.error {
+            Some(error) => Err(AppError::Llm(error)),
+            None => Ok(response.text),
+        }
+    }
+
+    async fn send_chat_request(&self, messages: &[Message]) -> Result<ResponseStream> {
+        tracing::debug!(
+            "Sending {} messages to Ollama ({:?} mode)",
+            messages.len(),
+            self.api_mode
+        );
+
+        let (url, request) = self.build_request(messages, self.stream, self.request_options());
+
+        if self.log_request_bodies {
+            let body = serde_json::to_string(&request)?;
+            tracing::debug!(
+                "Request body ({} bytes): {}",
+                body.len(),
+                self.log_truncation.apply(&body, MAX_LOGGED_BODY_BYTES)
+            );
+        }
 
         let resp = self
             .client
@@ -110,52 +820,404 @@ impl OllamaClient {
             .error_for_status()
             .map_err(|e| AppError::llm(format!("API error: {}", e)))?;
 
-        let stream = resp.bytes_stream().map(|item| {
-            let bytes = item.map_err(|e| AppError::Http(e))?;
+        if !self.stream {
+            return self.read_full_response(resp).await;
+        }
+
+        let parser = NdjsonParser::new(
+            Box::pin(resp.bytes_stream()),
+            self.api_mode,
+            self.stream_idle_timeout,
+            self.log_request_bodies,
+        );
+        let stream = futures_util::stream::unfold(parser, |mut parser| async move {
+            parser.next_chunk().await.map(|item| (item, parser))
+        });
+
+        Ok(self.track_stream_health(Box::pin(stream)))
+    }
+
+    /// Delivers a non-streaming response as a single final chunk.
+    async fn read_full_response(&self, resp: reqwest::Response) -> Result<ResponseStream> {
+        let body = resp.bytes().await?;
+        let response = ResponseFrame::parse(self.api_mode, &body)?;
+
+        if let Some(error) = response.error {
+            return Err(AppError::Llm(error));
+        }
+
+        let chunk = StreamChunk {
+            text: response.text,
+            done: true,
+            stats: response.stats,
+        };
+        Ok(Box::pin(futures_util::stream::once(
+            async move { Ok(chunk) },
+        )))
+    }
+}
 
-            let response: GenerateResponse =
-                serde_json::from_slice(&bytes).map_err(|e| AppError::Json(e))?;
+type ByteStream = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
 
-            if let Some(error) = response.error {
-                return Err(AppError::Llm(error));
+/// Splits the response body into newline-delimited JSON frames, which do not
+/// necessarily line up with the byte chunks reqwest hands us.
+struct NdjsonParser {
+    inner: futures_util::stream::Fuse<ByteStream>,
+    buffer: Vec<u8>,
+    finished: bool,
+    api_mode: ApiMode,
+    idle_timeout: Duration,
+    log_frames: bool,
+}
+
+impl NdjsonParser {
+    fn new(inner: ByteStream, api_mode: ApiMode, idle_timeout: Duration, log_frames: bool) -> Self {
+        Self {
+            inner: inner.fuse(),
+            buffer: Vec::new(),
+            finished: false,
+            api_mode,
+            idle_timeout,
+            log_frames,
+        }
+    }
+
+    async fn next_chunk(&mut self) -> Option<Result<StreamChunk>> {
+        loop {
+            if self.finished {
+                return None;
             }
 
-            Ok(StreamChunk {
-                text: response.response,
-                done: response.done,
-            })
-        });
+            if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                match self.parse_line(&line) {
+                    Some(Ok(chunk)) if chunk.done => {
+                        self.drain().await;
+                        return Some(Ok(chunk));
+                    }
+                    Some(item) => return Some(item),
+                    None => continue,
+                }
+            }
 
-        Ok(Box::pin(stream))
+            let next = match tokio::time::timeout(self.idle_timeout, self.inner.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    tracing::warn!("No data from Ollama for {:?}, giving up", self.idle_timeout);
+                    self.finished = true;
+                    return Some(Err(AppError::Timeout {
+                        seconds: self.idle_timeout.as_secs(),
+                    }));
+                }
+            };
+
+            match next {
+                Some(Ok(bytes)) => self.buffer.extend_from_slice(&bytes),
+                Some(Err(e)) => {
+                    self.finished = true;
+                    return Some(Err(AppError::Http(e)));
+                }
+                None if self.buffer.iter().all(u8::is_ascii_whitespace) => {
+                    tracing::warn!("Stream closed before a done frame was received");
+                    self.finished = true;
+                    return Some(Err(AppError::StreamEnded));
+                }
+                None => {
+                    // Treat an unterminated trailing frame as a complete line.
+                    self.buffer.push(b'\n');
+                }
+            }
+        }
+    }
+
+    /// Reads the body to its end after the done frame, so the connection can be
+    /// reused, but gives up after the idle timeout rather than hang on it.
+    async fn drain(&mut self) {
+        let mut ignored = 0;
+        loop {
+            match tokio::time::timeout(self.idle_timeout, self.inner.next()).await {
+                Ok(Some(Ok(bytes))) => ignored += bytes.len(),
+                Ok(Some(Err(e))) => {
+                    tracing::debug!("Error after done frame: {}", e);
+                    break;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    tracing::debug!("Body still open {:?} after done frame", self.idle_timeout);
+                    break;
+                }
+            }
+        }
+        if ignored > 0 {
+            tracing::debug!("Ignored {} bytes after done frame", ignored);
+        }
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> Option<Result<StreamChunk>> {
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            return None;
+        }
+
+        if self.log_frames {
+            tracing::trace!("Response frame: {}", String::from_utf8_lossy(line));
+        }
+
+        let response = match ResponseFrame::parse(self.api_mode, line) {
+            Ok(response) => response,
+            Err(e) => {
+                self.finished = true;
+                return Some(Err(AppError::Json(e)));
+            }
+        };
+
+        if let Some(error) = response.error {
+            self.finished = true;
+            return Some(Err(AppError::Llm(error)));
+        }
+
+        if response.done {
+            self.finished = true;
+            if !self.buffer.iter().all(u8::is_ascii_whitespace) {
+                tracing::debug!(
+                    "Ignoring {} bytes of trailing data after done",
+                    self.buffer.len()
+                );
+            }
+            self.buffer.clear();
+        }
+
+        Some(Ok(StreamChunk {
+            text: response.text,
+            done: response.done,
+            stats: response.stats,
+        }))
     }
 }
 
 #[async_trait]
 impl LlmProvider for OllamaClient {
     async fn health_check(&self) -> Result<()> {
+        if self.health_cached() {
+            tracing::debug!("Using cached health status for {}", self.base_url);
+            return Ok(());
+        }
+
         tracing::debug!("Cehck Ollama service health: {}", self.base_url);
 
-        self.client
-            .get(format!("{}/api/tags", self.base_url))
+        let url = format!("{}{}", self.base_url, self.health_path);
+        let request = match self.health_method {
+            HealthMethod::Get => self.client.get(url),
+            HealthMethod::Head => self.client.head(url),
+        };
+
+        let result = request
             .send()
             .await
-            .map_err(|e| AppError::service_unvailable(format!("connect failed: {}", e)))?
-            .error_for_status()
-            .map_err(|e| AppError::service_unvailable(format!("health check failed: {}", e)))?;
+            .map_err(|e| AppError::service_unvailable(format!("connect failed: {}", e)))
+            .and_then(|resp| {
+                resp.error_for_status().map_err(|e| {
+                    AppError::service_unvailable(format!("health check failed: {}", e))
+                })
+            });
+
+        self.set_healthy(result.is_ok());
+        result?;
 
         tracing::info!("Ollama service normal");
         Ok(())
     }
 
-    async fn chat(&self, prompt: &str) -> Result<ResponseStream> {
-        if prompt.trim().is_empty() {
+    async fn chat(&self, messages: &[Message]) -> Result<ResponseStream> {
+        self.chat_with_retries(messages, self.max_retries).await
+    }
+
+    async fn chat_with_retries(
+        &self,
+        messages: &[Message],
+        max_retries: u32,
+    ) -> Result<ResponseStream> {
+        if messages.iter().all(|m| m.content.trim().is_empty()) {
             return Err(AppError::invalid_input("Prompt can not be empty"));
         }
 
-        self.chat_stream_with_retry(prompt).await
+        self.chat_stream_with_retry(messages, max_retries).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        OllamaClient::list_models(self).await
+    }
+
+    async fn pull_model(&self) -> Result<PullStream> {
+        OllamaClient::pull_model(self).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        OllamaClient::embed(self, text).await
+    }
+
+    async fn context_window(&self) -> Result<Option<u64>> {
+        OllamaClient::context_window(self).await
+    }
+
+    /// Ollama has no `n`, so this sends `n` requests at once with different seeds.
+    async fn alternatives(&self, messages: &[Message], n: u32) -> Result<Vec<String>> {
+        let base_seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        tracing::debug!("Requesting {} alternatives from base seed {}", n, base_seed);
+
+        futures_util::future::try_join_all(
+            (0..u64::from(n)).map(|i| self.complete_with_seed(messages, base_seed + i)),
+        )
+        .await
+    }
+
+    async fn resolve_model(&mut self, preferences: &[String]) -> Result<bool> {
+        if !preferences.is_empty() {
+            self.select_preferred_model(preferences).await?;
+            return Ok(true);
+        }
+        Ok(self.validate_model().await)
+    }
+
+    fn set_model(&mut self, model: &str) -> Result<()> {
+        tracing::info!("Switching model: {} -> {}", self.model_name, model);
+        self.model_name = model.to_string();
+        Ok(())
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Tools and images also depend on the model, which Ollama rejects at request time.
+        ProviderCapabilities {
+            embeddings: true,
+            tools: true,
+            images: true,
+            streaming: true,
+        }
     }
 
     fn name(&self) -> &str {
         "ollama"
     }
+
+    fn describe(&self) -> String {
+        format!("ollama at {}", self.base_url)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+}
+
+/// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(body: &[&'static str]) -> NdjsonParser {
+        let chunks: Vec<reqwest::Result<Bytes>> =
+            body.iter().map(|chunk| Ok(Bytes::from(*chunk))).collect();
+        NdjsonParser::new(
+            Box::pin(futures_util::stream::iter(chunks)),
+            ApiMode::Chat,
+            Duration::from_secs(5),
+            false,
+        )
+    }
+
+    #[tokio::test]
+    async fn ignores_frames_after_done() {
+        let mut parser = parser(&[
+            "{\"message\":{\"content\":\"Hel\"},\"done\":false}\n{\"message\":",
+            "{\"content\":\"lo\"},\"done\":false}\n",
+            "{\"message\":{\"content\":\"\"},\"done\":true}\n{\"done\":true}\nnot json\n",
+        ]);
+
+        let first = parser.next_chunk().await.unwrap().unwrap();
+        assert_eq!((first.text.as_str(), first.done), ("Hel", false));
+        let second = parser.next_chunk().await.unwrap().unwrap();
+        assert_eq!((second.text.as_str(), second.done), ("lo", false));
+        let last = parser.next_chunk().await.unwrap().unwrap();
+        assert!(last.done);
+        assert!(parser.next_chunk().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_the_body_to_its_end_after_done() {
+        let mut parser = parser(&[
+            "{\"message\":{\"content\":\"Hi\"},\"done\":true}\n",
+            "{\"done\":true}\n",
+            "trailing",
+        ]);
+
+        let chunk = parser.next_chunk().await.unwrap().unwrap();
+        assert!(chunk.done);
+        assert!(parser.inner.is_done());
+        assert!(parser.next_chunk().await.is_none());
+    }
+
+    #[test]
+    fn context_window_prefers_num_ctx_over_the_trained_length() {
+        let show: ShowResponse = serde_json::from_str(
+            r#"{"parameters":"stop \"<|eot|>\"\nnum_ctx 8192","model_info":{"llama.context_length":131072}}"#,
+        )
+        .unwrap();
+        assert_eq!(show.context_window(), Some(8192));
+
+        let show: ShowResponse =
+            serde_json::from_str(r#"{"model_info":{"qwen2.context_length":32768}}"#).unwrap();
+        assert_eq!(show.context_window(), Some(32768));
+    }
+
+    #[test]
+    fn generate_prompt_continues_a_seeded_reply() {
+        let messages = [
+            Message::new(Role::User, "Hi"),
+            Message::new(Role::Assistant, "Sure,"),
+        ];
+
+        let prompt = OllamaClient::render_prompt(&messages);
+
+        assert_eq!(prompt, "User: Hi\n\nAssistant: Sure,");
+    }
+
+    #[tokio::test]
+    async fn reports_stream_ended_on_eof_without_done() {
+        let mut parser = parser(&["{\"message\":{\"content\":\"Hi\"},\"done\":false}\n"]);
+
+        let chunk = parser.next_chunk().await.unwrap().unwrap();
+        assert_eq!((chunk.text.as_str(), chunk.done), ("Hi", false));
+        assert!(matches!(
+            parser.next_chunk().await,
+            Some(Err(AppError::StreamEnded))
+        ));
+        assert!(parser.next_chunk().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn parses_an_unterminated_final_frame() {
+        let mut parser = parser(&["{\"message\":{\"content\":\"Hi\"},\"done\":true}"]);
+
+        let chunk = parser.next_chunk().await.unwrap().unwrap();
+        assert_eq!((chunk.text.as_str(), chunk.done), ("Hi", true));
+        assert!(parser.next_chunk().await.is_none());
+    }
 }
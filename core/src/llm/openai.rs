@@ -0,0 +1,343 @@
+use crate::config::OpenAiConfig;
+use crate::error::{AppError, Result};
+use crate::llm::{LlmProvider, Message, ProviderCapabilities, ResponseStream, StreamChunk};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use std::time::Duration;
+
+const DONE_MARKER: &str = "[DONE]";
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletion {
+    #[serde(default)]
+    choices: Vec<CompletionChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChoice {
+    message: CompletionMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: ChunkDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+pub struct OpenAiClient {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    model_name: String,
+    max_retries: u32,
+    temperature: Option<f32>,
+    stream_idle_timeout: Duration,
+}
+
+impl OpenAiClient {
+    pub fn new(cfg: &OpenAiConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(cfg.timeout_secs))
+            .build()?;
+
+        let api_key = cfg
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+            .filter(|key| !key.is_empty());
+        if api_key.is_none() {
+            tracing::warn!("No OpenAI API key configured, sending requests without one");
+        }
+
+        Ok(Self {
+            client,
+            base_url: cfg.base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model_name: cfg.model_name.clone(),
+            max_retries: cfg.max_retries,
+            temperature: cfg.temperature,
+            stream_idle_timeout: Duration::from_secs(cfg.stream_idle_timeout_secs),
+        })
+    }
+
+    async fn send_chat_request(&self, messages: &[Message]) -> Result<ResponseStream> {
+        tracing::debug!("Sending {} messages to {}", messages.len(), self.base_url);
+
+        let url = format!("{}/v1/chat/completions", self.base_url);
+        let request = ChatCompletionRequest {
+            model: &self.model_name,
+            messages,
+            stream: true,
+            temperature: self.temperature,
+            n: None,
+        };
+
+        let mut builder = self.client.post(&url).json(&request);
+        if let Some(ref api_key) = self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let resp = builder
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| AppError::llm(format!("API error: {}", e)))?;
+
+        let parser = SseParser::new(resp, self.stream_idle_timeout);
+        let stream = futures_util::stream::unfold(parser, |mut parser| async move {
+            parser.next_chunk().await.map(|item| (item, parser))
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    /// Asks for `n` choices in one unstreamed request.
+    async fn request_choices(&self, messages: &[Message], n: u32) -> Result<Vec<String>> {
+        let url = format!("{}/v1/chat/completions", self.base_url);
+        let request = ChatCompletionRequest {
+            model: &self.model_name,
+            messages,
+            stream: false,
+            temperature: self.temperature,
+            n: Some(n),
+        };
+
+        let mut builder = self.client.post(&url).json(&request);
+        if let Some(ref api_key) = self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let completion: ChatCompletion = builder
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| AppError::llm(format!("API error: {}", e)))?
+            .json()
+            .await?;
+
+        Ok(completion
+            .choices
+            .into_iter()
+            .map(|choice| choice.message.content.unwrap_or_default())
+            .collect())
+    }
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+
+/// Reads server-sent events, yielding the delta of each `data:` line until `[DONE]`.
+struct SseParser {
+    inner: futures_util::stream::Fuse<ByteStream>,
+    buffer: Vec<u8>,
+    finished: bool,
+    /// Longest gap allowed between two reads before the stream is abandoned.
+    idle_timeout: Duration,
+}
+
+impl SseParser {
+    fn new(resp: reqwest::Response, idle_timeout: Duration) -> Self {
+        let inner: ByteStream = Box::pin(resp.bytes_stream());
+        Self {
+            inner: inner.fuse(),
+            buffer: Vec::new(),
+            finished: false,
+            idle_timeout,
+        }
+    }
+
+    async fn next_chunk(&mut self) -> Option<Result<StreamChunk>> {
+        loop {
+            if self.finished {
+                return None;
+            }
+
+            if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                match self.parse_line(&line) {
+                    Some(item) => return Some(item),
+                    None => continue,
+                }
+            }
+
+            let next = match tokio::time::timeout(self.idle_timeout, self.inner.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    tracing::warn!(
+                        "No data from the server for {:?}, giving up",
+                        self.idle_timeout
+                    );
+                    self.finished = true;
+                    return Some(Err(AppError::Timeout {
+                        seconds: self.idle_timeout.as_secs(),
+                    }));
+                }
+            };
+
+            match next {
+                Some(Ok(bytes)) => self.buffer.extend_from_slice(&bytes),
+                Some(Err(e)) => {
+                    self.finished = true;
+                    return Some(Err(AppError::Http(e)));
+                }
+                None if self.buffer.iter().all(u8::is_ascii_whitespace) => {
+                    tracing::warn!("Stream closed before [DONE] was received");
+                    self.finished = true;
+                    return Some(Err(AppError::StreamEnded));
+                }
+                None => self.buffer.push(b'\n'),
+            }
+        }
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> Option<Result<StreamChunk>> {
+        let line = std::str::from_utf8(line.trim_ascii()).ok()?;
+        // Blank lines separate events; comments and other fields carry no content.
+        let data = line.strip_prefix("data:")?.trim();
+
+        if data == DONE_MARKER {
+            self.finished = true;
+            return Some(Ok(StreamChunk {
+                text: String::new(),
+                done: true,
+                stats: None,
+            }));
+        }
+
+        let chunk: ChatCompletionChunk = match serde_json::from_str(data) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                self.finished = true;
+                return Some(Err(AppError::Json(e)));
+            }
+        };
+
+        let text = chunk
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.delta.content)
+            .unwrap_or_default();
+
+        Some(Ok(StreamChunk {
+            text,
+            done: false,
+            stats: None,
+        }))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiClient {
+    async fn health_check(&self) -> Result<()> {
+        tracing::debug!("Check OpenAI-compatible service health: {}", self.base_url);
+
+        let mut builder = self.client.get(format!("{}/v1/models", self.base_url));
+        if let Some(ref api_key) = self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        builder
+            .send()
+            .await
+            .map_err(|e| AppError::service_unvailable(format!("connect failed: {}", e)))?
+            .error_for_status()
+            .map_err(|e| AppError::service_unvailable(format!("health check failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn chat(&self, messages: &[Message]) -> Result<ResponseStream> {
+        self.chat_with_retries(messages, self.max_retries).await
+    }
+
+    async fn chat_with_retries(
+        &self,
+        messages: &[Message],
+        max_retries: u32,
+    ) -> Result<ResponseStream> {
+        if messages.iter().all(|m| m.content.trim().is_empty()) {
+            return Err(AppError::invalid_input("Prompt can not be empty"));
+        }
+
+        let mut last_error = None;
+        for attempt in 1..=max_retries {
+            match self.send_chat_request(messages).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) if e.is_retryable() && attempt < max_retries => {
+                    tracing::warn!("Attempt {}/{} failed: {}", attempt, max_retries, e);
+                    last_error = Some(e);
+                    let delay = super::retry_delay(attempt);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or(AppError::RetryExhausted {
+            attempts: max_retries,
+        }))
+    }
+
+    async fn alternatives(&self, messages: &[Message], n: u32) -> Result<Vec<String>> {
+        self.request_choices(messages, n).await
+    }
+
+    fn set_model(&mut self, model: &str) -> Result<()> {
+        tracing::info!("Switching model: {} -> {}", self.model_name, model);
+        self.model_name = model.to_string();
+        Ok(())
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Only streaming chat is implemented for this API so far.
+        ProviderCapabilities {
+            streaming: true,
+            ..ProviderCapabilities::default()
+        }
+    }
+
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn describe(&self) -> String {
+        format!("openai at {}", self.base_url)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+}
@@ -1,41 +1,158 @@
 mod agent;
+mod cli;
 mod config;
 mod error;
 mod io;
 mod llm;
 
+use clap::Parser;
 use error::Result;
+use std::io::IsTerminal;
+use tokio::io::AsyncReadExt;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = cli::Cli::parse();
     init_logging()?;
 
+    if cli.list_audio_devices {
+        println!("Audio hosts:");
+        for host in io::VoiceInput::list_hosts() {
+            println!("  {}", host);
+        }
+        // Listing should work without a usable config, so fall back to defaults.
+        let cfg = match cli.config {
+            Some(ref path) => config::AppConfig::load_from_path(path)?,
+            None => config::AppConfig::load_or_default(),
+        };
+        let host = cfg.voice.as_ref().and_then(|v| v.audio_host.as_deref());
+        println!("Input devices ({} host):", host.unwrap_or("default"));
+        for device in io::VoiceInput::list_devices(host)? {
+            println!("  {}", device);
+        }
+        return Ok(());
+    }
+
     tracing::info!("Starting AI Chat application");
 
-    let cfg = config::AppConfig::load()?;
+    let mut cfg = match cli.config {
+        Some(ref path) => config::AppConfig::load_from_path(path)?,
+        None => config::AppConfig::load()?,
+    };
+    cli.apply_overrides(&mut cfg);
+    cfg.validate()?;
     tracing::debug!("Configuration: {:#?}", cfg);
 
-    let output = io::TextOutput::new();
-    let agent = agent::Agent::new(&cfg.ollama)?;
+    if let Some(ref path) = cli.transcribe_file {
+        let voice = cfg.voice.as_ref().ok_or_else(|| {
+            error::AppError::config("--transcribe-file needs a [voice] section in the config")
+        })?;
+        let input = io::VoiceInput::new(voice)?;
+        if voice.timestamps {
+            for segment in input.transcribe_file_detailed(path)?.segments {
+                println!(
+                    "[{} - {} ms] {}",
+                    segment.start_ms, segment.end_ms, segment.text
+                );
+            }
+        } else {
+            println!("{}", input.transcribe_file(path)?);
+        }
+        return Ok(());
+    }
+
+    let text_output = || io::TextOutput::new().with_error_codes(cfg.show_error_codes);
+    let output: Box<dyn io::OutputSink> = match cfg.output_fifo {
+        Some(ref path) => Box::new(io::FifoOutput::open(path).await?),
+        None => match cfg.output_format {
+            config::OutputFormat::Plain if cfg.highlight_code => {
+                Box::new(io::HighlightOutput::new(text_output()))
+            }
+            config::OutputFormat::Plain => Box::new(text_output()),
+            config::OutputFormat::Markdown => Box::new(io::MarkdownOutput::new(text_output())),
+            config::OutputFormat::Jsonl => Box::new(io::JsonOutput::new()),
+        },
+    };
+    let agent = agent::Agent::new(&cfg)?;
+
+    if let Some(prompt) = one_shot_prompt(&cli).await? {
+        tracing::info!("one-shot mode start!");
+        return exit_on_error(agent::run_once(output, agent, &prompt, &cfg).await);
+    }
+
+    if let Some(ref path) = cli.replay {
+        tracing::info!("replay mode start!");
+        let turns = agent::read_turns(path)?;
+        return match agent::run_replay(turns, output, agent, &cfg).await {
+            Ok(true) => Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => exit_on_error(Err(e)),
+        };
+    }
+
+    if let Some(ref path) = cli.batch_file {
+        tracing::info!("batch mode start!");
+        let input = io::BatchInput::from_file(path, &cli.batch_delimiter, cfg.blank_lines.batch)?;
+        return exit_on_error(agent::run_batch(input, output, agent, &cfg).await);
+    }
 
     if let Some(ref voice_ref) = cfg.voice {
         tracing::info!("voic mode start!");
-        let input = io::VoiceInput::new(voice_ref)?;
-        run_with_input(input, output, agent).await
+        let mut input = io::VoiceInput::new(voice_ref)?;
+        if voice_ref.interim_results {
+            let mut interim = input.interim_results();
+            tokio::spawn(async move {
+                while let Some(text) = interim.recv().await {
+                    eprintln!("🎤 {}…", text);
+                }
+            });
+        }
+        let output: Box<dyn io::OutputSink> = if voice_ref.speak_responses {
+            Box::new(io::VoiceOutput::new(output, voice_ref))
+        } else {
+            output
+        };
+        run_with_input(input, output, agent, &cfg).await
     } else {
         tracing::info!("text mode start!");
-        let input = io::TextInput::new();
-        run_with_input(input, output, agent).await
+        if std::io::stdin().is_terminal() {
+            let input = io::RichTextInput::new(&cfg)?;
+            return run_with_input(input, output, agent, &cfg).await;
+        }
+        let input = io::TextInput::new()
+            .lossy_utf8(cfg.input_lossy_utf8)
+            .blank_lines(cfg.blank_lines.stdin)
+            .terminator(cfg.input_terminator.as_deref());
+        run_with_input(input, output, agent, &cfg).await
     }
 }
 
+/// The prompt from the arguments or piped input, if the app should answer once and exit.
+async fn one_shot_prompt(cli: &cli::Cli) -> Result<Option<String>> {
+    if cli.stdin {
+        let mut prompt = String::new();
+        tokio::io::stdin().read_to_string(&mut prompt).await?;
+        return Ok(Some(prompt));
+    }
+
+    if cli.prompt.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(cli.prompt.join(" ")))
+}
+
 async fn run_with_input(
     input: impl io::InputSource,
     output: impl io::OutputSink,
     agent: agent::Agent,
+    cfg: &config::AppConfig,
 ) -> Result<()> {
-    match agent::run(input, output, agent).await {
+    exit_on_error(agent::run(input, output, agent, cfg).await)
+}
+
+fn exit_on_error(result: Result<()>) -> Result<()> {
+    match result {
         Ok(_) => {
             tracing::info!("Application exited normally");
             Ok(())